@@ -1,13 +1,38 @@
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+static SQUARES: [(usize, usize); 64] = {
+    let mut squares = [(0, 0); 64];
+    let mut i = 0;
+    while i < 64 {
+        squares[i] = ((i % 8) + 1, i / 8 + 1);
+        i += 1;
+    }
+    squares
+};
+
+const MATE_SCORE: i32 = 1_000_000;
+
+// the main line of each opening load_opening knows by name
+const OPENINGS: [(&str, &[&str]); 5] = [
+    ("Ruy Lopez", &["e4", "e5", "Nf3", "Nc6", "Bb5"]),
+    ("Italian", &["e4", "e5", "Nf3", "Nc6", "Bc4"]),
+    ("Sicilian", &["e4", "c5"]),
+    ("French", &["e4", "e6"]),
+    ("Queen's Gambit", &["d4", "d5", "c4"]),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Color {
     White,
     Black,
 }
 
 impl Color {
-    fn opposite(&self) -> Self {
+    pub fn opposite(&self) -> Self {
         match self {
             Color::White => Color::Black,
             Color::Black => Color::White,
@@ -15,7 +40,8 @@ impl Color {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PieceType {
     King,
     Queen,
@@ -25,27 +51,422 @@ pub enum PieceType {
     Pawn,
 }
 
+impl PieceType {
+    pub fn value(&self) -> u32 {
+        match self {
+            PieceType::Pawn => 1,
+            PieceType::Knight | PieceType::Bishop => 3,
+            PieceType::Rook => 5,
+            PieceType::Queen => 9,
+            PieceType::King => 0,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Castle {
     KingSide,
     QueenSide,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Check {
     Check,
     Checkmate,
 }
 
+// the commentary glyphs PGN writes after a move
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Annotation {
+    Good,
+    Mistake,
+    Brilliant,
+    Blunder,
+    Interesting,
+    Dubious,
+}
+
+impl Annotation {
+    pub fn glyph(&self) -> &'static str {
+        match self {
+            Annotation::Good => "!",
+            Annotation::Mistake => "?",
+            Annotation::Brilliant => "!!",
+            Annotation::Blunder => "??",
+            Annotation::Interesting => "!?",
+            Annotation::Dubious => "?!",
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GameState {
     InProgress,
     Checkmate(Color),
     Check(Color),
+    Draw(DrawReason),
+    // the side that gave up
+    Resigned(Color),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DrawReason {
     Stalemate,
+    // claimed by a player
+    FiftyMove,
+    ThreefoldRepetition,
+    // automatic, with no claim needed
+    SeventyFiveMove,
+    FivefoldRepetition,
+    InsufficientMaterial,
+    Agreement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CastlingRights {
+    pub white_king_side: bool,
+    pub white_queen_side: bool,
+    pub black_king_side: bool,
+    pub black_queen_side: bool,
+    // files the king and rooks start on, which can differ from e, h and a in Chess960
+    king_file: usize,
+    king_side_rook_file: usize,
+    queen_side_rook_file: usize,
+}
+
+// home files only matter while there's still a right that castles with them
+impl PartialEq for CastlingRights {
+    fn eq(&self, other: &Self) -> bool {
+        let rights = |rights: &Self| {
+            [Color::White, Color::Black].map(|color| {
+                [Castle::KingSide, Castle::QueenSide].map(|castle| {
+                    rights
+                        .can_castle(color, castle)
+                        .then(|| (rights.king_file, rights.rook_file(castle)))
+                })
+            })
+        };
+        rights(self) == rights(other)
+    }
+}
+
+impl CastlingRights {
+    pub fn all() -> Self {
+        Self {
+            white_king_side: true,
+            white_queen_side: true,
+            black_king_side: true,
+            black_queen_side: true,
+            king_file: 5,
+            king_side_rook_file: 8,
+            queen_side_rook_file: 1,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            white_king_side: false,
+            white_queen_side: false,
+            black_king_side: false,
+            black_queen_side: false,
+            ..Self::all()
+        }
+    }
+
+    fn chess960(king_file: usize, queen_side_rook_file: usize, king_side_rook_file: usize) -> Self {
+        Self {
+            king_file,
+            king_side_rook_file,
+            queen_side_rook_file,
+            ..Self::all()
+        }
+    }
+
+    pub fn king_file(&self) -> usize {
+        self.king_file
+    }
+
+    pub fn rook_file(&self, castle: Castle) -> usize {
+        match castle {
+            Castle::KingSide => self.king_side_rook_file,
+            Castle::QueenSide => self.queen_side_rook_file,
+        }
+    }
+
+    fn is_standard(&self) -> bool {
+        (self.king_file, self.king_side_rook_file, self.queen_side_rook_file) == (5, 8, 1)
+    }
+
+    pub fn can_castle(&self, color: Color, castle: Castle) -> bool {
+        match (color, castle) {
+            (Color::White, Castle::KingSide) => self.white_king_side,
+            (Color::White, Castle::QueenSide) => self.white_queen_side,
+            (Color::Black, Castle::KingSide) => self.black_king_side,
+            (Color::Black, Castle::QueenSide) => self.black_queen_side,
+        }
+    }
+
+    fn right_mut(&mut self, color: Color, castle: Castle) -> &mut bool {
+        match (color, castle) {
+            (Color::White, Castle::KingSide) => &mut self.white_king_side,
+            (Color::White, Castle::QueenSide) => &mut self.white_queen_side,
+            (Color::Black, Castle::KingSide) => &mut self.black_king_side,
+            (Color::Black, Castle::QueenSide) => &mut self.black_queen_side,
+        }
+    }
+
+    fn revoke(&mut self, color: Color, castle: Castle) {
+        *self.right_mut(color, castle) = false;
+    }
+
+    // drops any right whose king or rook home square was moved from or onto
+    fn update(&mut self, from: (usize, usize), to: (usize, usize)) {
+        for (color, home_row) in [(Color::White, 1), (Color::Black, 8)] {
+            for (col, row) in [from, to] {
+                if row != home_row {
+                    continue;
+                }
+                if col == self.king_file {
+                    self.revoke(color, Castle::KingSide);
+                    self.revoke(color, Castle::QueenSide);
+                } else if col == self.king_side_rook_file {
+                    self.revoke(color, Castle::KingSide);
+                } else if col == self.queen_side_rook_file {
+                    self.revoke(color, Castle::QueenSide);
+                }
+            }
+        }
+    }
+
+    fn from_pieces(pieces: &Board) -> Self {
+        let mut rights = Self::all();
+        for (color, home_row) in [(Color::White, 1), (Color::Black, 8)] {
+            let is_home = |coords: (usize, usize), piece_type: PieceType| {
+                pieces.get(&coords) == Some(&Piece::new(piece_type, color))
+            };
+            if !is_home((5, home_row), PieceType::King) {
+                rights.revoke(color, Castle::KingSide);
+                rights.revoke(color, Castle::QueenSide);
+            }
+            if !is_home((8, home_row), PieceType::Rook) {
+                rights.revoke(color, Castle::KingSide);
+            }
+            if !is_home((1, home_row), PieceType::Rook) {
+                rights.revoke(color, Castle::QueenSide);
+            }
+        }
+        rights
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Board {
+    squares: [Option<Piece>; 64],
+}
+
+impl Board {
+    pub fn empty() -> Self {
+        Self { squares: [None; 64] }
+    }
+
+    pub fn get(&self, coords: &(usize, usize)) -> Option<&Piece> {
+        self.squares[square_index(*coords)?].as_ref()
+    }
+
+    pub fn contains_key(&self, coords: &(usize, usize)) -> bool {
+        self.get(coords).is_some()
+    }
+
+    pub fn insert(&mut self, coords: (usize, usize), piece: Piece) -> Option<Piece> {
+        let index = square_index(coords).expect("Coordinates must be on the board");
+        self.squares[index].replace(piece)
+    }
+
+    pub fn remove(&mut self, coords: &(usize, usize)) -> Option<Piece> {
+        self.squares[square_index(*coords)?].take()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&(usize, usize), &Piece)> + '_ {
+        SQUARES.iter()
+            .zip(self.squares.iter())
+            .filter_map(|(coords, square)| square.as_ref().map(|piece| (coords, piece)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Piece> + '_ {
+        self.squares.iter().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the piece placement field of a FEN string
+    fn placement(&self) -> String {
+        let mut placement = String::new();
+        for row in (1..=8).rev() {
+            let mut empty = 0;
+            for col in 1..=8 {
+                match self.get(&(col, row)) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece.fen_char());
+                    }
+                    None => {
+                        empty += 1;
+                    }
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if row > 1 {
+                placement.push('/');
+            }
+        }
+        placement
+    }
+
+    fn from_placement(placement: &str) -> Option<Board> {
+        let rows = placement.split('/').collect::<Vec<_>>();
+        if rows.len() != 8 {
+            return None;
+        }
+        let mut pieces = Board::empty();
+        for (i, row_notation) in rows.iter().enumerate() {
+            let row = 8 - i;
+            let mut col = 1;
+            for c in row_notation.chars() {
+                if let Some(empty) = c.to_digit(10) {
+                    if !(1..=8).contains(&empty) {
+                        return None;
+                    }
+                    col += empty as usize;
+                    continue;
+                }
+                let piece = Piece::try_from(c).ok()?;
+                if col > 8 {
+                    return None;
+                }
+                pieces.insert((col, row), piece);
+                col += 1;
+            }
+            if col != 9 {
+                return None;
+            }
+        }
+        Some(pieces)
+    }
+}
+
+impl FromIterator<((usize, usize), Piece)> for Board {
+    fn from_iter<I: IntoIterator<Item = ((usize, usize), Piece)>>(iter: I) -> Self {
+        let mut board = Board::empty();
+        for (coords, piece) in iter {
+            board.insert(coords, piece);
+        }
+        board
+    }
+}
+
+// boards serialize as the piece placement field of a FEN string
+#[cfg(feature = "serde")]
+impl serde::Serialize for Board {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.placement())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Board {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let placement = String::deserialize(deserializer)?;
+        Board::from_placement(&placement).ok_or_else(|| {
+            serde::de::Error::custom(format!("invalid piece placement: {}", placement))
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a Board {
+    type Item = (&'a (usize, usize), &'a Piece);
+    type IntoIter = Box<dyn Iterator<Item = Self::Item> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Square {
+    file: usize,
+    rank: usize,
+}
+
+impl Square {
+    pub fn file(&self) -> usize {
+        self.file
+    }
+
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+}
+
+impl TryFrom<(usize, usize)> for Square {
+    type Error = ChessError;
+
+    fn try_from(coords: (usize, usize)) -> Result<Self, Self::Error> {
+        let (file, rank) = coords;
+        if square_index(coords).is_none() {
+            return Err(ChessError::InvalidSquare);
+        }
+        Ok(Self { file, rank })
+    }
+}
+
+impl From<Square> for (usize, usize) {
+    fn from(square: Square) -> Self {
+        (square.file, square.rank)
+    }
+}
+
+impl std::str::FromStr for Square {
+    type Err = ChessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Square::try_from(notation_to_coords(s).ok_or(ChessError::InvalidSquare)?)
+    }
+}
+
+impl Display for Square {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // a Square is always on the board
+        write!(f, "{}", coords_to_notation((self.file, self.rank)).unwrap_or_default())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub piece_type: PieceType,
     pub color: Color,
@@ -67,10 +488,36 @@ impl Piece {
         }
     }
 
+    // the letter FEN uses, uppercase for white and lowercase for black
+    pub fn fen_char(&self) -> char {
+        match self.color {
+            Color::White => self.letter(),
+            Color::Black => self.letter().to_ascii_lowercase(),
+        }
+    }
+
+    pub fn unicode(&self) -> char {
+        match (self.color, self.piece_type) {
+            (Color::White, PieceType::King) => '♔',
+            (Color::White, PieceType::Queen) => '♕',
+            (Color::White, PieceType::Rook) => '♖',
+            (Color::White, PieceType::Bishop) => '♗',
+            (Color::White, PieceType::Knight) => '♘',
+            (Color::White, PieceType::Pawn) => '♙',
+            (Color::Black, PieceType::King) => '♚',
+            (Color::Black, PieceType::Queen) => '♛',
+            (Color::Black, PieceType::Rook) => '♜',
+            (Color::Black, PieceType::Bishop) => '♝',
+            (Color::Black, PieceType::Knight) => '♞',
+            (Color::Black, PieceType::Pawn) => '♟',
+        }
+    }
+
     pub fn get_possible_moves(
         &self,
         piece_coords: (usize, usize),
-        pieces_on_board: &HashMap<(usize, usize), Self>
+        pieces_on_board: &Board,
+        en_passant: Option<(usize, usize)>
     ) -> Vec<Command> {
         let (piece_x, piece_y) = piece_coords;
         let from = (Some(piece_x), Some(piece_y));
@@ -83,10 +530,14 @@ impl Piece {
                     Color::Black => 7,
                 };
                 let pawn_steps = if piece_y == pawn_row { 1..3 } else { 1..2 };
+                // a pawn blocked on its first step can't advance two squares either
+                let mut blocked = false;
                 for step in pawn_steps {
                     if let Some(new_y) = pawn_move(piece_y, step, self.color) {
-                        if pieces_on_board.get(&(piece_x, new_y)).is_none() {
-                            moves.push(command_builder.to((piece_x, new_y)).build());
+                        if !blocked && pieces_on_board.get(&(piece_x, new_y)).is_none() {
+                            moves.extend(pawn_commands(command_builder, (piece_x, new_y)));
+                        } else {
+                            blocked = true;
                         }
                         // can also calculate capture when step is 1
                         if step == 1 {
@@ -102,6 +553,14 @@ impl Piece {
                                 }) {
                                 match pieces_on_board.get(&possible_capture) {
                                     Some(piece) if piece.color != self.color => {
+                                        moves.extend(
+                                            pawn_commands(
+                                                command_builder.takes(true),
+                                                possible_capture
+                                            )
+                                        );
+                                    }
+                                    None if en_passant == Some(possible_capture) => {
                                         moves.push(
                                             command_builder.takes(true).to(possible_capture).build()
                                         );
@@ -119,6 +578,20 @@ impl Piece {
                         if x < 1 || x > 8 || y < 1 || y > 8 {
                             continue;
                         }
+                        // kings can never stand next to each other
+                        if
+                            self.piece_type == PieceType::King &&
+                            pieces_on_board
+                                .iter()
+                                .any(|(coords, piece)| {
+                                    piece.piece_type == PieceType::King &&
+                                        piece.color != self.color &&
+                                        coords.0.abs_diff(x) <= 1 &&
+                                        coords.1.abs_diff(y) <= 1
+                                })
+                        {
+                            continue;
+                        }
                         let takes;
                         match pieces_on_board.get(&(x, y)) {
                             Some(piece) if piece.color != self.color => {
@@ -137,7 +610,7 @@ impl Piece {
             }
             PieceType::Bishop | PieceType::Queen | PieceType::Rook => {
                 let directions = self.get_direction_vectors();
-                for direction in directions {
+                for &direction in directions {
                     let mut step = 1;
                     loop {
                         if let Some(next_coords) = next_coords(piece_coords, direction, step) {
@@ -171,24 +644,22 @@ impl Piece {
         &self,
         piece_coords: (usize, usize),
         target_coords: (usize, usize),
-        pieces_on_board: &HashMap<(usize, usize), Self>,
+        pieces_on_board: &Board,
         takes: bool
     ) -> bool {
+        // staying put isn't a move, though a slider finds nothing between a square and itself
+        if piece_coords == target_coords {
+            return false;
+        }
         let (from_x, from_y) = piece_coords;
         let (to_x, to_y) = target_coords;
         match self.piece_type {
             PieceType::Pawn => {
                 match takes {
                     true => {
-                        if
-                            to_x.abs_diff(from_x) == 1 &&
-                            to_y ==
-                                (if self.color == Color::White { from_y + 1 } else { from_y - 1 })
-                        {
-                            return true;
-                        } else {
-                            return false;
-                        }
+                        // pawns only capture on the two diagonals in their direction of travel
+                        return to_x.abs_diff(from_x) == 1 &&
+                            pawn_move(from_y, 1, self.color) == Some(to_y);
                     }
                     false => {
                         if
@@ -199,7 +670,13 @@ impl Piece {
                                         Color::White => 2,
                                         Color::Black => 7,
                                     }) &&
-                                    to_y == pawn_move(from_y, 2, self.color).unwrap_or_default()))
+                                    to_y == pawn_move(from_y, 2, self.color).unwrap_or_default() &&
+                                    !pieces_on_board.contains_key(
+                                        &(
+                                            from_x,
+                                            pawn_move(from_y, 1, self.color).unwrap_or_default(),
+                                        )
+                                    )))
                         {
                             return true;
                         } else {
@@ -235,6 +712,7 @@ impl Piece {
                     to_y != from_y &&
                     to_x.abs_diff(from_x) != to_y.abs_diff(from_y)
                 {
+                    return false;
                 }
             }
             PieceType::Rook => {
@@ -249,32 +727,15 @@ impl Piece {
             }
         }
 
-        let (direction_x, direction_y) = (to_x.cmp(&from_x) as isize, to_y.cmp(&from_y) as isize);
-        let mut i = 1;
-        loop {
-            let coords = match next_coords((from_x, from_y), (direction_x, direction_y), i) {
-                Some(coords) => coords,
-                None => {
-                    break;
-                }
-            };
-            if coords == (to_x, to_y) {
-                return true;
-            }
-            if pieces_on_board.contains_key(&coords) {
-                break;
-            }
-            i += 1;
-        }
-        false
+        between(piece_coords, target_coords).all(|coords| !pieces_on_board.contains_key(&coords))
     }
 
-    fn get_direction_vectors(&self) -> Vec<(isize, isize)> {
+    fn get_direction_vectors(&self) -> &'static [(isize, isize)] {
         match self.piece_type {
-            PieceType::Bishop => vec![(1, 1), (1, -1), (-1, 1), (-1, -1)],
-            PieceType::Rook => vec![(1, 0), (-1, 0), (0, 1), (0, -1)],
+            PieceType::Bishop => &[(1, 1), (1, -1), (-1, 1), (-1, -1)],
+            PieceType::Rook => &[(1, 0), (-1, 0), (0, 1), (0, -1)],
             PieceType::Queen =>
-                vec![(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)],
+                &[(1, 1), (1, -1), (-1, 1), (-1, -1), (1, 0), (-1, 0), (0, 1), (0, -1)],
             _ => panic!("Only bishops, rooks, and queens move with a direction vector"),
         }
     }
@@ -314,14 +775,84 @@ impl Piece {
     }
 }
 
+impl TryFrom<char> for Piece {
+    type Error = ChessError;
+
+    fn try_from(c: char) -> Result<Self, Self::Error> {
+        let piece_type = match c.to_ascii_uppercase() {
+            'K' => PieceType::King,
+            'Q' => PieceType::Queen,
+            'R' => PieceType::Rook,
+            'B' => PieceType::Bishop,
+            'N' => PieceType::Knight,
+            'P' => PieceType::Pawn,
+            _ => {
+                return Err(ChessError::InvalidFen);
+            }
+        };
+        let color = if c.is_ascii_uppercase() { Color::White } else { Color::Black };
+        Ok(Piece::new(piece_type, color))
+    }
+}
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Game {
     pub turn: Color,
-    pub pieces: HashMap<(usize, usize), Piece>,
+    pub pieces: Board,
     pub state: GameState,
+    pub en_passant: Option<(usize, usize)>,
+    pub castling_rights: CastlingRights,
+    pub halfmove_clock: usize,
+    pub fullmove_number: usize,
+    // half-moves played since the game was set up
+    ply: usize,
+    pub history: Vec<Move>,
+    // pieces taken so far, in the order they were captured
+    pub captured: Vec<Piece>,
+    // positions before each move in `history`, without their own history
+    snapshots: Vec<Game>,
+    // cached so is_check doesn't have to search the board
+    #[cfg_attr(feature = "serde", serde(skip))]
+    white_king: Option<(usize, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    black_king: Option<(usize, usize)>,
+}
+
+// a move as it was actually made, with everything a command may leave out worked out from the
+// board it was played on
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Move {
+    pub piece: PieceType,
+    // for castling, the king's squares
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub captured: Option<Piece>,
+    // the piece a pawn became, including the default queen
+    pub promotion: Option<PieceType>,
+    pub castle: Option<Castle>,
+    pub en_passant: bool,
+}
+
+impl Move {
+    // a command naming both squares, so it never needs disambiguating
+    pub fn to_command(&self) -> Command {
+        if let Some(castle) = self.castle {
+            return CommandBuilder::new().piece(PieceType::King).castle(Some(castle)).build();
+        }
+        CommandBuilder::new()
+            .piece(self.piece)
+            .from((Some(self.from.0), Some(self.from.1)))
+            .to(self.to)
+            .takes(self.captured.is_some())
+            .promotion(self.promotion)
+            .build()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Command {
     pub piece: PieceType,
     pub from: (Option<usize>, Option<usize>),
@@ -329,6 +860,9 @@ pub struct Command {
     pub takes: bool,
     pub check: Option<Check>,
     pub castle: Option<Castle>,
+    pub promotion: Option<PieceType>,
+    // commentary only, never consulted when playing the move
+    pub annotation: Option<Annotation>,
 }
 
 #[derive(Copy, Clone)]
@@ -339,6 +873,14 @@ pub struct CommandBuilder {
     takes: Option<bool>,
     check: Option<Check>,
     castle: Option<Castle>,
+    promotion: Option<PieceType>,
+    annotation: Option<Annotation>,
+}
+
+impl Default for CommandBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CommandBuilder {
@@ -350,6 +892,8 @@ impl CommandBuilder {
             takes: None,
             check: None,
             castle: None,
+            promotion: None,
+            annotation: None,
         }
     }
 
@@ -383,15 +927,48 @@ impl CommandBuilder {
         self
     }
 
+    pub fn promotion(mut self, promotion: Option<PieceType>) -> Self {
+        self.promotion = promotion;
+        self
+    }
+
+    pub fn annotation(mut self, annotation: Option<Annotation>) -> Self {
+        self.annotation = annotation;
+        self
+    }
+
     pub fn build(self) -> Command {
         Command {
             piece: self.piece.unwrap(),
-            to: self.to.unwrap_or((0, 0)),
+            // castling is the only move that doesn't name its target square; it gets an off-board
+            // one that the serializers never read, since they find a castle's squares on the board
+            to: match (self.to, self.castle) {
+                (Some(to), _) => to,
+                (None, Some(_)) => (0, 0),
+                (None, None) => panic!("Only castling can be built without a target square"),
+            },
             from: self.from.unwrap_or((None, None)),
             takes: self.takes.unwrap_or(false),
             check: self.check,
             castle: self.castle,
+            promotion: self.promotion,
+            annotation: self.annotation,
+        }
+    }
+
+    // like build, but turns away a source or target square off the board
+    pub fn try_build(self) -> Result<Command, ChessError> {
+        let (from_col, from_row) = self.from.unwrap_or((None, None));
+        let on_board = self.to
+            .into_iter()
+            .flat_map(|(x, y)| [x, y])
+            .chain(from_col)
+            .chain(from_row)
+            .all(|coord| (1..=8).contains(&coord));
+        if !on_board {
+            return Err(ChessError::InvalidSquare);
         }
+        Ok(self.build())
     }
 }
 
@@ -405,19 +982,42 @@ impl Command {
         }
         lazy_static! {
             static ref NOTATION_PATTERN: Regex = Regex::new(
-                r"^(?P<piece>[NBRQK])?(?P<from_col>[a-h])?(?P<from_row>[1-8])?(?P<takes>x)?(?P<to>[a-h][1-8])(?P<promotion>=[NBRQK])?(?P<check>\+|#)?$|^(?P<castle>O-O|O-O-O)?$"
+                r"^(?:(?P<castle>O-O-O|O-O|0-0-0|0-0)|(?P<piece>[NBRQK])?(?P<from_col>[a-h])?(?P<from_row>[1-8])?(?P<takes>x)?(?P<to>[a-h][1-8])(?P<promotion>=[NBRQ])?)(?P<check>\+|#)?(?P<annotation>!!|\?\?|!\?|\?!|!|\?)?$"
             ).unwrap();
         }
         let command_builder = CommandBuilder::new();
         let captures = NOTATION_PATTERN.captures(input)?;
-        if input == "O-O" {
-            return Some(
-                command_builder.piece(PieceType::King).castle(Some(Castle::KingSide)).build()
-            );
-        }
-        if input == "O-O-O" {
+        let check = match captures.name("check") {
+            Some(check) => {
+                match check.as_str() {
+                    "+" => Some(Check::Check),
+                    "#" => Some(Check::Checkmate),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+        let annotation = match captures.name("annotation").map(|annotation| annotation.as_str()) {
+            Some("!") => Some(Annotation::Good),
+            Some("?") => Some(Annotation::Mistake),
+            Some("!!") => Some(Annotation::Brilliant),
+            Some("??") => Some(Annotation::Blunder),
+            Some("!?") => Some(Annotation::Interesting),
+            Some("?!") => Some(Annotation::Dubious),
+            _ => None,
+        };
+        if let Some(castle) = captures.name("castle") {
+            let castle = match castle.as_str() {
+                "O-O" | "0-0" => Castle::KingSide,
+                _ => Castle::QueenSide,
+            };
             return Some(
-                command_builder.piece(PieceType::King).castle(Some(Castle::QueenSide)).build()
+                command_builder
+                    .piece(PieceType::King)
+                    .castle(Some(castle))
+                    .check(check)
+                    .annotation(annotation)
+                    .build()
             );
         }
         let piece = match captures.name("piece") {
@@ -440,28 +1040,31 @@ impl Command {
             None
         };
         let from_row = if let Some(from_row) = captures.name("from_row") {
-            Some(from_row.as_str().parse::<usize>().unwrap() - 1)
+            Some(from_row.as_str().parse::<usize>().unwrap())
         } else {
             None
         };
         let takes = captures.name("takes").is_some();
         if from_row.is_some() || from_col.is_some() {
-            if
-                (piece != PieceType::Knight &&
-                    piece != PieceType::Rook &&
-                    piece != PieceType::Pawn) ||
-                (!takes && piece == PieceType::Pawn)
-            {
+            // only one king, and a pawn names its file only when it captures
+            if piece == PieceType::King || (!takes && piece == PieceType::Pawn) {
                 return None;
             }
         }
         let to = captures.name("to").unwrap().as_str();
-        let check = match captures.name("check") {
-            Some(check) => {
-                match check.as_str() {
-                    "+" => Some(Check::Check),
-                    "#" => Some(Check::Checkmate),
-                    _ => None,
+        let promotion = match captures.name("promotion") {
+            Some(promotion) => {
+                if piece != PieceType::Pawn {
+                    return None;
+                }
+                match promotion.as_str() {
+                    "=N" => Some(PieceType::Knight),
+                    "=B" => Some(PieceType::Bishop),
+                    "=R" => Some(PieceType::Rook),
+                    "=Q" => Some(PieceType::Queen),
+                    _ => {
+                        return None;
+                    }
                 }
             }
             None => None,
@@ -474,22 +1077,48 @@ impl Command {
                 .from((from_col, from_row))
                 .takes(takes)
                 .check(check)
+                .promotion(promotion)
+                .annotation(annotation)
                 .build()
         )
     }
 
-    pub fn to_notation(&self) -> String {
+    // None for a move onto one of the mover's own pieces, unless it's a Chess960 king taking its
+    // rook to castle
+    pub fn parse_uci(input: &str, game: &Game) -> Option<Self> {
+        lazy_static! {
+            static ref UCI_PATTERN: Regex = Regex::new(
+                r"^(?P<from>[a-h][1-8])(?P<to>[a-h][1-8])(?P<promotion>[qrbn])?$"
+            ).unwrap();
+        }
+        let captures = UCI_PATTERN.captures(input)?;
+        let from = notation_to_coords(captures.name("from").unwrap().as_str())?;
+        let to = notation_to_coords(captures.name("to").unwrap().as_str())?;
+        let promotion = match captures.name("promotion").map(|promotion| promotion.as_str()) {
+            Some("q") => Some(PieceType::Queen),
+            Some("r") => Some(PieceType::Rook),
+            Some("b") => Some(PieceType::Bishop),
+            Some("n") => Some(PieceType::Knight),
+            _ => None,
+        };
+        game.build_move(from, to, promotion)
+    }
+
+    // None when the command can't be written as SAN without a board, like a pawn capture that
+    // doesn't name its file
+    pub fn to_notation(&self) -> Option<String> {
         let suffix = match self.check {
             Some(Check::Check) => "+",
             Some(Check::Checkmate) => "#",
             None => "",
         };
+        let glyph = self.annotation.map_or("", |annotation| annotation.glyph());
         match self.castle {
             Some(Castle::KingSide) => {
-                return format!("O-O{}", suffix);
+                return Some(format!("O-O{}{}", suffix, glyph));
             }
             Some(Castle::QueenSide) => {
-                return format!("O-O-O,{}", suffix);
+                return Some(format!("O-O-O{}{}", suffix, glyph));
             }
             _ => {}
         }
@@ -504,31 +1133,177 @@ impl Command {
                 PieceType::Rook => 'R',
                 _ => unreachable!(),
             });
-        } else {
-            if self.takes {
-                notation.push(
-                    column_index_to_letter(
-                        self.from.0.expect("Must have a-h specified for pawn takes")
-                    )
-                );
-            }
+        } else if self.takes {
+            notation.push(column_index_to_letter(self.from.0?)?);
         }
         if self.takes {
             notation.push('x');
         }
-        notation.push_str(coords_to_notation(self.to).as_str());
+        notation.push_str(&coords_to_notation(self.to)?);
+        if let Some(promotion) = self.promotion {
+            notation.push('=');
+            notation.push(Piece::new(promotion, Color::White).letter());
+        }
         notation.push_str(suffix);
-        notation
+        notation.push_str(glyph);
+        Some(notation)
     }
-}
-
-#[derive(Debug, PartialEq, Eq)]
-pub enum ChessError {
-    InvalidMove,
-    InCheck,
-}
 
-use std::fmt::{ Display, Formatter };
+    pub fn to_notation_on(&self, game: &Game) -> Result<String, ChessError> {
+        if self.piece == PieceType::Pawn
+            && self.castle.is_none()
+            && self.takes
+            && self.from.0.is_none()
+        {
+            // a capture that doesn't name its file takes it from the one pawn that can make it;
+            // pushes onto the target square don't count
+            let sources = self
+                .legal_sources(game)
+                .into_iter()
+                .filter(|coords| coords.0 != self.to.0 && coords_match_from(*coords, self.from))
+                .collect::<Vec<_>>();
+            let from = match sources.as_slice() {
+                [] => {
+                    return Err(ChessError::InvalidMove);
+                }
+                [from] => *from,
+                _ => {
+                    return Err(ChessError::AmbiguousMove);
+                }
+            };
+            let command = Command { from: (Some(from.0), self.from.1), ..self.clone() };
+            return command.to_notation().ok_or(ChessError::InvalidSquare);
+        }
+        let mut notation = self.to_notation().ok_or(ChessError::InvalidSquare)?;
+        if self.castle.is_some() || self.piece == PieceType::Pawn {
+            return Ok(notation);
+        }
+        let legal_moves = self.legal_sources(game);
+        let from = match self.source_on(&legal_moves) {
+            Some(from) => from,
+            None => {
+                return Ok(notation);
+            }
+        };
+        let rivals = legal_moves
+            .iter()
+            .filter(|coords| **coords != from)
+            .collect::<Vec<_>>();
+        let square = coords_to_notation(from).ok_or(ChessError::InvalidSquare)?;
+        let disambiguation = if rivals.is_empty() {
+            ""
+        } else if rivals.iter().all(|coords| coords.0 != from.0) {
+            &square[..1]
+        } else if rivals.iter().all(|coords| coords.1 != from.1) {
+            &square[1..]
+        } else {
+            &square
+        };
+        notation.insert_str(1, disambiguation);
+        Ok(notation)
+    }
+
+    // None when the squares the move goes between can't be found on the board
+    pub fn to_uci(&self, game: &Game) -> Option<String> {
+        if let Some(castle) = self.castle {
+            let home_row = match game.turn {
+                Color::White => 1,
+                Color::Black => 8,
+            };
+            let rights = &game.castling_rights;
+            let to_col = match (rights.is_standard(), castle) {
+                (false, _) => rights.rook_file(castle),
+                (true, Castle::KingSide) => 7,
+                (true, Castle::QueenSide) => 3,
+            };
+            return Some(format!(
+                "{}{}",
+                coords_to_notation((rights.king_file(), home_row))?,
+                coords_to_notation((to_col, home_row))?
+            ));
+        }
+        let from = match self.from {
+            (Some(x), Some(y)) => (x, y),
+            _ => self.source_on(&self.legal_sources(game))?,
+        };
+        let mut uci = coords_to_notation(from)?;
+        uci.push_str(&coords_to_notation(self.to)?);
+        if let Some(promotion) = self.promotion {
+            uci.push(Piece::new(promotion, Color::Black).letter().to_ascii_lowercase());
+        }
+        Some(uci)
+    }
+
+    // squares holding a piece that can legally make this move, ignoring `from`
+    fn legal_sources(&self, game: &Game) -> Vec<(usize, usize)> {
+        game.get_all_possible_moves(game.turn)
+            .into_iter()
+            .filter(|command| {
+                command.castle.is_none() && command.piece == self.piece && command.to == self.to
+            })
+            .filter_map(|command| {
+                match command.from {
+                    (Some(x), Some(y)) => Some((x, y)),
+                    _ => None,
+                }
+            })
+            .fold(Vec::new(), |mut sources, coords| {
+                // promotions list the same source once per piece type
+                if !sources.contains(&coords) {
+                    sources.push(coords);
+                }
+                sources
+            })
+    }
+
+    fn source_on(&self, legal_sources: &[(usize, usize)]) -> Option<(usize, usize)> {
+        let mut sources = legal_sources
+            .iter()
+            .filter(|coords| coords_match_from(**coords, self.from));
+        match (sources.next(), sources.next()) {
+            (Some(from), None) => Some(*from),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Command {
+    type Err = ChessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim().len() < 2 {
+            return Err(ChessError::NotationTooShort);
+        }
+        Command::parse(s.trim()).ok_or(ChessError::InvalidNotation)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChessError {
+    InvalidMove,
+    InCheck,
+    AmbiguousMove,
+    CastleThroughCheck,
+    InvalidFen,
+    NothingToUndo,
+    InvalidSquare,
+    InvalidPosition,
+    NoPieceToMove,
+    WrongTurn,
+    PathBlocked,
+    IllegalPromotion,
+    NotationTooShort,
+    InvalidNotation,
+    GameOver,
+    NoDrawToClaim,
+    // a move in a list failed, with its position in the list
+    MoveListFailed {
+        index: usize,
+        error: Box<ChessError>,
+    },
+}
+
+use std::fmt::{ Display, Formatter };
 
 impl std::error::Error for ChessError {}
 
@@ -541,29 +1316,54 @@ impl Display for ChessError {
                     f,
                     "Cannot move into check. If you are in check, you must move out of check"
                 ),
+            ChessError::AmbiguousMove =>
+                write!(f, "Ambiguous move. Specify the file or rank of the piece to move"),
+            ChessError::CastleThroughCheck =>
+                write!(f, "Cannot castle out of, through, or into check"),
+            ChessError::InvalidFen => write!(f, "Invalid FEN string"),
+            ChessError::NothingToUndo => write!(f, "There are no moves to undo"),
+            ChessError::InvalidSquare => write!(f, "Invalid square"),
+            ChessError::InvalidPosition =>
+                write!(
+                    f,
+                    "Illegal position. Check the kings, pawns on back ranks, and who is in check"
+                ),
+            ChessError::NoPieceToMove => write!(f, "There is no piece there to make that move"),
+            ChessError::WrongTurn => write!(f, "It is not that piece's turn to move"),
+            ChessError::PathBlocked => write!(f, "Another piece is in the way"),
+            ChessError::IllegalPromotion =>
+                write!(f, "Only pawns reaching the last rank can promote, and not to a king"),
+            ChessError::NotationTooShort => write!(f, "Move is too short, e.g. try e4 or Nf3"),
+            ChessError::InvalidNotation => write!(f, "Move is not in algebraic notation"),
+            ChessError::GameOver => write!(f, "The game is already over"),
+            ChessError::NoDrawToClaim =>
+                write!(f, "There is no threefold repetition or fifty-move draw to claim"),
+            ChessError::MoveListFailed { index, error } =>
+                write!(f, "Move {} could not be played: {}", index + 1, error),
         }
     }
 }
 
 impl Display for Game {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let mut board = String::new();
-        board.push_str("-".repeat(17).as_str());
-        board.push_str("\n");
-        for row in 1..=8 {
-            board.push('|');
-            for col in 1..=8 {
-                board.push(match self.pieces.get(&(col, 9 - row)) {
-                    Some(piece) => piece.letter(),
-                    None => ' ',
-                });
-                board.push('|');
-            }
-            board.push_str("\n");
-            board.push_str("-".repeat(17).as_str());
-            board.push_str("\n");
-        }
-        writeln!(f, "{}", board)
+        writeln!(f, "{}", self.render(Color::White, f.alternate()))
+    }
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// two games are equal when they describe the same position, however they got there
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.turn == other.turn &&
+            self.state == other.state &&
+            self.pieces == other.pieces &&
+            self.en_passant == other.en_passant &&
+            self.castling_rights == other.castling_rights
     }
 }
 
@@ -607,242 +1407,1686 @@ impl Game {
             ]
                 .iter()
                 .cloned()
-                .collect::<HashMap<(usize, usize), Piece>>(),
+                .collect::<Board>(),
             state: GameState::InProgress,
+            en_passant: None,
+            castling_rights: CastlingRights::all(),
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            ply: 0,
+            history: vec![],
+            captured: vec![],
+            snapshots: vec![],
+            white_king: Some((5, 1)),
+            black_king: Some((5, 8)),
+        }
+    }
+
+    // the back rank for a Chess960 position id, numbered as in Scharnagl's scheme (518 is
+    // the standard starting position)
+    pub fn new_960(position_id: u16) -> Game {
+        assert!(position_id < 960, "Chess960 position ids run from 0 to 959");
+        let mut back_rank = [None; 8];
+        let mut id = position_id as usize;
+        // bishops first, one on a light square and one on a dark square
+        back_rank[(id % 4) * 2 + 1] = Some(PieceType::Bishop);
+        id /= 4;
+        back_rank[(id % 4) * 2] = Some(PieceType::Bishop);
+        id /= 4;
+        let mut place_on_empty = |nth: usize, piece_type: PieceType| {
+            let file = (0..8).filter(|file| back_rank[*file].is_none()).nth(nth).unwrap();
+            back_rank[file] = Some(piece_type);
+        };
+        place_on_empty(id % 6, PieceType::Queen);
+        id /= 6;
+        let (first_knight, second_knight) = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ][id];
+        // the second knight is counted before the first one fills a square
+        place_on_empty(second_knight, PieceType::Knight);
+        place_on_empty(first_knight, PieceType::Knight);
+        // the king always sits between the two rooks
+        for piece_type in [PieceType::Rook, PieceType::King, PieceType::Rook] {
+            place_on_empty(0, piece_type);
+        }
+
+        let back_rank = back_rank.map(|piece_type| piece_type.unwrap());
+        let mut pieces = Board::empty();
+        for (file, piece_type) in (1..=8).zip(back_rank) {
+            pieces.insert((file, 1), Piece::new(piece_type, Color::White));
+            pieces.insert((file, 2), Piece::new(PieceType::Pawn, Color::White));
+            pieces.insert((file, 7), Piece::new(PieceType::Pawn, Color::Black));
+            pieces.insert((file, 8), Piece::new(piece_type, Color::Black));
+        }
+        let files_of = |wanted: PieceType| {
+            (1..=8).filter(move |file| back_rank[file - 1] == wanted)
+        };
+        let king_file = files_of(PieceType::King).next().unwrap();
+        let rook_files = files_of(PieceType::Rook).collect::<Vec<_>>();
+        Game {
+            pieces,
+            castling_rights: CastlingRights::chess960(king_file, rook_files[0], rook_files[1]),
+            white_king: Some((king_file, 1)),
+            black_king: Some((king_file, 8)),
+            ..Game::new()
         }
     }
 
     pub fn from(pieces: HashMap<(usize, usize), Piece>, turn: Color) -> Game {
+        let pieces = pieces.into_iter().collect::<Board>();
         Game {
+            castling_rights: CastlingRights::from_pieces(&pieces),
+            white_king: find_king(&pieces, Color::White),
+            black_king: find_king(&pieces, Color::Black),
             pieces,
             turn,
             state: GameState::InProgress,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            ply: 0,
+            history: vec![],
+            captured: vec![],
+            snapshots: vec![],
+        }
+    }
+
+    // the board as seen from `perspective`'s side of the table
+    pub fn display_for(&self, perspective: Color) -> String {
+        self.render(perspective, false)
+    }
+
+    fn render(&self, perspective: Color, figurines: bool) -> String {
+        let (rows, cols): (Vec<usize>, Vec<usize>) = match perspective {
+            Color::White => ((1..=8).rev().collect(), (1..=8).collect()),
+            Color::Black => ((1..=8).collect(), (1..=8).rev().collect()),
+        };
+        let divider = format!("  {}\n", "-".repeat(17));
+        let mut board = divider.clone();
+        for row in rows {
+            board.push_str(&format!("{} |", row));
+            for col in cols.iter() {
+                board.push(match self.pieces.get(&(*col, row)) {
+                    // `{:#}` draws figurines so the two sides can be told apart
+                    Some(piece) if figurines => piece.unicode(),
+                    Some(piece) => piece.letter(),
+                    None => ' ',
+                });
+                board.push('|');
+            }
+            board.push('\n');
+            board.push_str(&divider);
+        }
+        board.push_str("  ");
+        for col in cols {
+            board.push(' ');
+            board.extend(column_index_to_letter(col));
+        }
+        board.push('\n');
+        board
+    }
+
+    pub fn new_empty(turn: Color) -> Game {
+        Game::from(HashMap::new(), turn)
+    }
+
+    pub fn place_piece(&mut self, coords: (usize, usize), piece: Piece) {
+        if square_index(coords).is_none() {
+            return;
+        }
+        if piece.piece_type == PieceType::King {
+            self.set_king_position(piece.color, coords);
+        }
+        if let Some(replaced) = self.pieces.insert(coords, piece) {
+            // a king or rook knocked off its home square can't castle any more
+            if replaced != piece {
+                self.castling_rights.update(coords, coords);
+            }
         }
+        self.state = self.get_game_state();
+    }
+
+    pub fn remove_piece(&mut self, coords: (usize, usize)) -> Option<Piece> {
+        let piece = self.pieces.remove(&coords);
+        if piece.is_some() {
+            self.castling_rights.update(coords, coords);
+        }
+        self.state = self.get_game_state();
+        piece
     }
 
     pub fn simulate_move(&self, input: &Command) -> Result<Self, ChessError> {
-        let mut new_board = self.clone();
-        let Command { to, from, piece, takes, castle, .. } = input;
+        self.simulate(input).map(|(game, _)| game)
+    }
+
+    // works out which piece a command moves and what it takes, without playing it
+    pub fn resolve(&self, command: &Command) -> Result<Move, ChessError> {
+        self.simulate(command).map(|(_, resolved)| resolved)
+    }
+
+    fn simulate(&self, input: &Command) -> Result<(Self, Move), ChessError> {
+        let mut new_board = self.without_history();
+        let Command { to, from, piece, takes, castle, promotion, .. } = input;
         let Game { turn: color, .. } = new_board;
+        new_board.en_passant = None;
+        if *piece == PieceType::Pawn || *takes {
+            new_board.halfmove_clock = 0;
+        } else {
+            new_board.halfmove_clock += 1;
+        }
+        if color == Color::Black {
+            new_board.fullmove_number += 1;
+        }
 
+        let is_en_passant = *piece == PieceType::Pawn && self.en_passant == Some(*to);
         match self.pieces.get(&to) {
-            Some(_) => {
-                if !takes {
+            Some(target) => {
+                // a piece never takes one of its own side
+                if !takes || target.color == color {
                     return Err(ChessError::InvalidMove);
                 }
             }
             None => {
-                if *takes {
+                if *takes && !is_en_passant {
                     return Err(ChessError::InvalidMove);
                 }
             }
         }
 
-        if let Some(castle) = castle {
-            if piece != &PieceType::King {
+        let resolved = if let Some(castle) = castle {
+            if piece != &PieceType::King || !self.castling_rights.can_castle(color, *castle) {
                 return Err(ChessError::InvalidMove);
             }
-            let rook_col = match castle {
-                Castle::QueenSide => 1,
-                Castle::KingSide => 8,
-            };
             let home_row = match color {
                 Color::White => 1,
                 Color::Black => 8,
             };
-            let (from_king, from_rook) = ((5, home_row), (rook_col, home_row));
-            if self.pieces.get(&from_king).is_none() || self.pieces.get(&from_rook).is_none() {
+            let from_king = (self.castling_rights.king_file(), home_row);
+            let from_rook = (self.castling_rights.rook_file(*castle), home_row);
+            if
+                self.pieces.get(&from_king) != Some(&Piece::new(PieceType::King, color)) ||
+                self.pieces.get(&from_rook) != Some(&Piece::new(PieceType::Rook, color))
+            {
                 return Err(ChessError::InvalidMove);
             }
+            // the king and rook land on the same squares in Chess960 as in standard chess
             let (to_king, to_rook) = match castle {
                 Castle::QueenSide => ((3, home_row), (4, home_row)),
                 Castle::KingSide => ((7, home_row), (6, home_row)),
             };
-            if self.pieces.get(&to_king).is_some() || self.pieces.get(&to_rook).is_some() {
-                return Err(ChessError::InvalidMove);
+            // every square either piece crosses must be empty, apart from the two of them
+            let king_path = from_king.0.min(to_king.0)..=from_king.0.max(to_king.0);
+            let rook_path = from_rook.0.min(to_rook.0)..=from_rook.0.max(to_rook.0);
+            for col in king_path.clone().chain(rook_path) {
+                let square = (col, home_row);
+                if square != from_king && square != from_rook && self.pieces.contains_key(&square) {
+                    return Err(ChessError::PathBlocked);
+                }
             }
-            let range = match castle {
-                Castle::QueenSide => 2..5,
-                Castle::KingSide => 6..8,
-            };
-            for col in range {
-                if self.pieces.get(&(col, home_row)).is_some() {
-                    return Err(ChessError::InvalidMove);
+            // the king can't castle out of, through, or into check
+            for col in king_path {
+                if self.is_square_attacked((col, home_row), color.opposite()) {
+                    return Err(ChessError::CastleThroughCheck);
                 }
             }
             let king = new_board.pieces.remove(&from_king).unwrap();
             let rook = new_board.pieces.remove(&from_rook).unwrap();
             new_board.pieces.insert(to_king, king);
             new_board.pieces.insert(to_rook, rook);
+            new_board.set_king_position(color, to_king);
+            new_board.castling_rights.update(from_king, to_king);
+            Move {
+                piece: PieceType::King,
+                from: from_king,
+                to: to_king,
+                captured: None,
+                promotion: None,
+                castle: Some(*castle),
+                en_passant: false,
+            }
         } else {
-            for (coords, candidate_piece) in self.pieces
+            if
+                promotion.is_some() &&
+                (*piece != PieceType::Pawn ||
+                    (to.1 != 1 && to.1 != 8) ||
+                    matches!(promotion, Some(PieceType::King | PieceType::Pawn)))
+            {
+                return Err(ChessError::IllegalPromotion);
+            }
+            let mut candidates = self.pieces
                 .iter()
                 .filter(|(coords, p)| {
                     coords_match_from(**coords, *from) && p.piece_type == *piece && p.color == color
-                }) {
-                if candidate_piece.can_move(*coords, *to, &self.pieces, *takes) {
-                    new_board.pieces.remove(&to);
-                    new_board.pieces.insert(*to, candidate_piece.clone());
-                    new_board.pieces.remove(coords);
+                })
+                .filter(|(coords, p)| p.can_move(**coords, *to, &self.pieces, *takes))
+                .map(|(coords, _)| *coords);
+            let from_coords = match (candidates.next(), candidates.next()) {
+                (None, _) => {
+                    return Err(self.unreachable_reason(input));
+                }
+                (Some(coords), None) => coords,
+                (Some(first), Some(second)) => {
+                    // a piece that can't legally move doesn't need to be disambiguated
+                    let mut legal_candidates = [first, second]
+                        .into_iter()
+                        .chain(candidates)
+                        .filter(|coords| {
+                            let mut board = new_board.clone();
+                            board.move_piece(*coords, input, is_en_passant);
+                            !board.is_check(color)
+                        });
+                    match (legal_candidates.next(), legal_candidates.next()) {
+                        (None, _) => {
+                            return Err(ChessError::InCheck);
+                        }
+                        (Some(coords), None) => coords,
+                        (Some(_), Some(_)) => {
+                            return Err(ChessError::AmbiguousMove);
+                        }
+                    }
                 }
+            };
+            let captured = new_board.move_piece(from_coords, input, is_en_passant);
+            new_board.castling_rights.update(from_coords, *to);
+            let promoted = match new_board.pieces.get(to) {
+                Some(moved) if moved.piece_type != *piece => Some(moved.piece_type),
+                _ => None,
+            };
+            Move {
+                piece: *piece,
+                from: from_coords,
+                to: *to,
+                captured,
+                promotion: promoted,
+                castle: None,
+                en_passant: is_en_passant && *takes,
             }
-        }
+        };
 
         if new_board.is_check(new_board.turn) {
             return Err(ChessError::InCheck);
         }
 
-        Ok(new_board)
+        Ok((new_board, resolved))
+    }
+
+    // works out why no piece could make a move, for a more helpful error
+    fn unreachable_reason(&self, command: &Command) -> ChessError {
+        let Command { to, from, piece, takes, .. } = command;
+        if let (Some(x), Some(y)) = from {
+            match self.pieces.get(&(*x, *y)) {
+                None => {
+                    return ChessError::NoPieceToMove;
+                }
+                Some(p) if p.piece_type == *piece && p.color != self.turn => {
+                    return ChessError::WrongTurn;
+                }
+                _ => {}
+            }
+        }
+        let mut pieces = self.pieces
+            .iter()
+            .filter(|(coords, p)| {
+                coords_match_from(**coords, *from) &&
+                    p.piece_type == *piece &&
+                    p.color == self.turn
+            })
+            .peekable();
+        let has_piece = pieces.peek().is_some();
+        // a piece that could make the move on an empty board is being blocked
+        let blocked = pieces.any(|(coords, p)| {
+            let lone_piece = Board::from_iter([(*coords, *p)]);
+            p.can_move(*coords, *to, &lone_piece, *takes)
+        });
+        let opponent_could_move = self.pieces
+            .iter()
+            .filter(|(coords, p)| {
+                coords_match_from(**coords, *from) &&
+                    p.piece_type == *piece &&
+                    p.color != self.turn
+            })
+            .any(|(coords, p)| p.can_move(*coords, *to, &self.pieces, *takes));
+        if blocked {
+            ChessError::PathBlocked
+        } else if opponent_could_move {
+            ChessError::WrongTurn
+        } else if has_piece {
+            ChessError::InvalidMove
+        } else {
+            ChessError::NoPieceToMove
+        }
+    }
+
+    // returns the piece taken, if any
+    fn move_piece(
+        &mut self,
+        from: (usize, usize),
+        command: &Command,
+        is_en_passant: bool
+    ) -> Option<Piece> {
+        let Command { to, piece, takes, promotion, .. } = command;
+        let mut captured = None;
+        if *takes && is_en_passant {
+            // the captured pawn sits beside the capturing pawn, not on the target
+            captured = self.pieces.remove(&(to.0, from.1));
+        }
+        let mut moved_piece = self.pieces.remove(&from)?;
+        if *piece == PieceType::Pawn && (to.1 == 1 || to.1 == 8) {
+            // pawns promote to a queen unless told otherwise
+            moved_piece.piece_type = promotion.unwrap_or(PieceType::Queen);
+        }
+        if let Some(taken) = self.pieces.insert(*to, moved_piece) {
+            captured = Some(taken);
+        }
+        if moved_piece.piece_type == PieceType::King {
+            self.set_king_position(moved_piece.color, *to);
+        }
+        if *piece == PieceType::Pawn && to.1.abs_diff(from.1) == 2 {
+            self.en_passant = Some((to.0, (to.1 + from.1) / 2));
+        }
+        captured
     }
 
     pub fn play(&mut self, command: &Command) -> Result<(), ChessError> {
-        let new_game = self.simulate_move(command)?;
+        if self.is_game_over() {
+            return Err(ChessError::GameOver);
+        }
+        let (mut new_game, resolved) = self.simulate(command)?;
+
+        new_game.history = std::mem::take(&mut self.history);
+        new_game.captured = std::mem::take(&mut self.captured);
+        new_game.snapshots = std::mem::take(&mut self.snapshots);
+        new_game.history.push(resolved);
+        new_game.captured.extend(resolved.captured);
+        new_game.snapshots.push(self.without_history());
 
         *self = new_game;
         self.next_turn();
+        self.ply += 1;
 
         self.state = self.get_game_state();
+        if !matches!(self.state, GameState::Checkmate(_)) {
+            if self.is_fivefold_repetition() {
+                self.state = GameState::Draw(DrawReason::FivefoldRepetition);
+            } else if self.is_seventy_five_move_rule() {
+                self.state = GameState::Draw(DrawReason::SeventyFiveMove);
+            } else if self.is_insufficient_material() {
+                self.state = GameState::Draw(DrawReason::InsufficientMaterial);
+            }
+        }
 
         Ok(())
     }
 
-    fn next_turn(&mut self) {
-        self.turn = match self.turn {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
-        };
+    pub fn last_move(&self) -> Option<&Move> {
+        self.history.last()
     }
 
-    pub fn get_game_state(&self) -> GameState {
-        let all_moves = self.get_all_possible_moves(self.turn);
-        let is_check = self.is_check(self.turn);
+    // where the last move went from and to; the king's squares for castling
+    pub fn last_move_squares(&self) -> Option<((usize, usize), (usize, usize))> {
+        self.last_move().map(|last| (last.from, last.to))
+    }
 
-        if is_check && all_moves.len() == 0 {
-            GameState::Checkmate(self.turn.opposite())
-        } else if is_check && all_moves.len() != 0 {
-            GameState::Check(self.turn)
-        } else if !is_check && all_moves.len() == 0 {
-            GameState::Stalemate
-        } else {
-            GameState::InProgress
-        }
+    pub fn ply_count(&self) -> usize {
+        self.ply
     }
 
-    pub fn is_check(&self, color_in_check: Color) -> bool {
-        let king_coords = match
-            self.pieces
-                .iter()
-                .find(
-                    |(_, piece)|
-                        piece.piece_type == PieceType::King && piece.color == color_in_check
-                )
-        {
-            Some((coords, _)) => coords,
-            None => {
-                return false;
-            }
-        };
-        let attacking_color = match color_in_check {
-            Color::White => Color::Black,
-            Color::Black => Color::White,
-        };
-        for (piece_coords, piece) in self.pieces
-            .iter()
-            .filter(|(_, piece)| piece.color == attacking_color) {
-            if piece.can_move(*piece_coords, *king_coords, &self.pieces, true) {
-                return true;
-            }
+    pub fn resign(&mut self, color: Color) -> Result<(), ChessError> {
+        if self.is_game_over() {
+            return Err(ChessError::GameOver);
         }
-        false
+        self.state = GameState::Resigned(color);
+        Ok(())
     }
 
-    pub fn get_all_possible_moves(&self, color: Color) -> Vec<Command> {
-        self.pieces
-            .iter()
-            .filter(|(_, Piece { color: _color, .. })| { _color == &color })
-            .flat_map(|(coords, piece)| { piece.get_possible_moves(*coords, &self.pieces) })
-            .filter_map(|command| {
-                match self.simulate_move(&command) {
-                    Ok(game) => {
-                        match game.is_check(self.turn.opposite()) {
-                            true =>
-                                Some(Command {
-                                    check: Some(Check::Check),
-                                    ..command
-                                }),
-                            _ => { Some(command) }
-                        }
-                    }
-                    Err(_) => { None }
-                }
-            })
-            .collect()
+    // threefold repetition and the fifty-move rule only end the game once a player claims them
+    pub fn can_claim_draw(&self) -> bool {
+        !self.is_game_over() && (self.is_threefold_repetition() || self.is_fifty_move_rule())
     }
-}
 
-fn notation_to_coords(notation: &str) -> Option<(usize, usize)> {
-    let mut chars = notation.chars();
-    let x = (chars.next().unwrap() as usize) - ('a' as usize) + 1;
-    let y = (chars.next().unwrap() as usize) - ('1' as usize) + 1;
-    if x > 8 || y > 8 {
-        return None;
+    pub fn claim_draw(&mut self) -> Result<(), ChessError> {
+        if self.is_game_over() {
+            return Err(ChessError::GameOver);
+        }
+        if self.is_threefold_repetition() {
+            self.state = GameState::Draw(DrawReason::ThreefoldRepetition);
+        } else if self.is_fifty_move_rule() {
+            self.state = GameState::Draw(DrawReason::FiftyMove);
+        } else {
+            return Err(ChessError::NoDrawToClaim);
+        }
+        Ok(())
     }
-    Some((x, y))
-}
 
-fn letter_to_column_index(letter: char) -> usize {
-    let letter = letter.to_ascii_lowercase();
-    if letter < 'a' || letter > 'h' {
-        panic!("How did we get here? I thought we checked this already.");
+    pub fn agree_draw(&mut self) -> Result<(), ChessError> {
+        if self.is_game_over() {
+            return Err(ChessError::GameOver);
+        }
+        self.state = GameState::Draw(DrawReason::Agreement);
+        Ok(())
     }
-    (letter as usize) - ('a' as usize) + 1
-}
 
-fn column_index_to_letter(col: usize) -> char {
-    if col < 1 || col > 8 {
-        panic!();
+    // plays each move in order, keeping the ones before the first that fails
+    pub fn apply_moves(&mut self, moves: &[&str]) -> Result<(), ChessError> {
+        for (index, notation) in moves.iter().enumerate() {
+            notation
+                .parse::<Command>()
+                .and_then(|command| self.play(&command))
+                .map_err(|error| ChessError::MoveListFailed { index, error: Box::new(error) })?;
+        }
+        Ok(())
     }
-    let char = col - 1 + ('a' as usize);
-    char as u8 as char
-}
-
-fn coords_to_notation(coords: (usize, usize)) -> String {
-    let x = (coords.0 as u8) + ('a' as u8) - 1;
-    let y = (coords.1 as u8) + ('1' as u8) - 1;
-    format!("{}{}", x as char, y as char)
-}
 
-fn coords_match_from(coords: (usize, usize), from: (Option<usize>, Option<usize>)) -> bool {
-    match from {
-        (Some(x), None) => coords.0 == x,
-        (None, Some(y)) => coords.1 == y,
-        (Some(x), Some(y)) => coords.0 == x && coords.1 == y,
-        _ => true,
+    // the position after a well-known opening, looked up by name regardless of case
+    pub fn load_opening(name: &str) -> Option<Game> {
+        let (_, moves) = OPENINGS.iter().find(|(opening, _)| opening.eq_ignore_ascii_case(name))?;
+        let mut game = Game::new();
+        game.apply_moves(moves).ok()?;
+        Some(game)
     }
-}
 
-fn next_coords(
-    origin: (usize, usize),
-    direction: (isize, isize),
-    step: isize
-) -> Option<(usize, usize)> {
-    let (x, y) = origin;
-    let (direction_x, direction_y) = direction;
-    let x = (x as isize) + direction_x * step;
-    let y = (y as isize) + direction_y * step;
-    if x < 1 || x > 8 || y < 1 || y > 8 {
-        return None;
+    pub fn undo(&mut self) -> Result<(), ChessError> {
+        let previous = self.snapshots.pop().ok_or(ChessError::NothingToUndo)?;
+        let undone = self.history.pop();
+        if undone.is_some_and(|undone| undone.captured.is_some()) {
+            self.captured.pop();
+        }
+
+        let history = std::mem::take(&mut self.history);
+        let captured = std::mem::take(&mut self.captured);
+        let snapshots = std::mem::take(&mut self.snapshots);
+        *self = previous;
+        self.history = history;
+        self.captured = captured;
+        self.snapshots = snapshots;
+
+        Ok(())
     }
-    Some((x as usize, y as usize))
-}
 
-fn pawn_move(y_coord: usize, step: isize, color: Color) -> Option<usize> {
-    let direction = if color == Color::White { 1 } else { -1 };
-    let new_y = (y_coord as isize) + step * direction;
+    // rewinds to an earlier ply, e.g. undo_to(0) goes back to the starting position
+    pub fn undo_to(&mut self, ply: usize) -> Result<(), ChessError> {
+        if ply > self.ply {
+            return Err(ChessError::NothingToUndo);
+        }
+        while self.ply > ply {
+            self.undo()?;
+        }
+        Ok(())
+    }
 
-    if new_y < 1 || new_y > 8 {
-        None
-    } else {
-        Some(new_y as usize)
+    // clones the position alone, so lookahead doesn't copy the whole game record
+    fn without_history(&self) -> Game {
+        Game {
+            turn: self.turn,
+            pieces: self.pieces,
+            state: self.state,
+            en_passant: self.en_passant,
+            castling_rights: self.castling_rights,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            ply: self.ply,
+            history: vec![],
+            captured: vec![],
+            snapshots: vec![],
+            white_king: self.white_king,
+            black_king: self.black_king,
+        }
     }
-}
\ No newline at end of file
+
+    pub fn from_fen(fen: &str) -> Result<Game, ChessError> {
+        let fields = fen.split_whitespace().collect::<Vec<_>>();
+        if fields.len() != 6 {
+            return Err(ChessError::InvalidFen);
+        }
+
+        let pieces = Board::from_placement(fields[0]).ok_or(ChessError::InvalidFen)?;
+        for color in [Color::White, Color::Black] {
+            let kings = pieces
+                .values()
+                .filter(|piece| **piece == Piece::new(PieceType::King, color))
+                .count();
+            if kings != 1 {
+                return Err(ChessError::InvalidFen);
+            }
+        }
+
+        let turn = match fields[1] {
+            "w" => Color::White,
+            "b" => Color::Black,
+            _ => {
+                return Err(ChessError::InvalidFen);
+            }
+        };
+
+        let mut castling_rights = CastlingRights::none();
+        if fields[2] != "-" {
+            for c in fields[2].chars() {
+                let color = match c.is_ascii_uppercase() {
+                    true => Color::White,
+                    false => Color::Black,
+                };
+                let castle = match c.to_ascii_lowercase() {
+                    'k' => Castle::KingSide,
+                    'q' => Castle::QueenSide,
+                    // Shredder-FEN names the rook's file instead, for Chess960 positions
+                    file @ 'a'..='h' => {
+                        let rook_file = ((file as u8) - b'a' + 1) as usize;
+                        let king_file = find_king(&pieces, color).unwrap().0;
+                        castling_rights.king_file = king_file;
+                        if rook_file > king_file {
+                            castling_rights.king_side_rook_file = rook_file;
+                            Castle::KingSide
+                        } else if rook_file < king_file {
+                            castling_rights.queen_side_rook_file = rook_file;
+                            Castle::QueenSide
+                        } else {
+                            return Err(ChessError::InvalidFen);
+                        }
+                    }
+                    _ => {
+                        return Err(ChessError::InvalidFen);
+                    }
+                };
+                let right = castling_rights.right_mut(color, castle);
+                if *right {
+                    return Err(ChessError::InvalidFen);
+                }
+                *right = true;
+            }
+        }
+
+        let en_passant = match fields[3] {
+            "-" => None,
+            square => {
+                match notation_to_coords(square) {
+                    Some(coords) if coords.1 == 3 || coords.1 == 6 => Some(coords),
+                    _ => {
+                        return Err(ChessError::InvalidFen);
+                    }
+                }
+            }
+        };
+
+        let halfmove_clock = fields[4].parse::<usize>().map_err(|_| ChessError::InvalidFen)?;
+        let fullmove_number = fields[5].parse::<usize>().map_err(|_| ChessError::InvalidFen)?;
+        if fullmove_number == 0 {
+            return Err(ChessError::InvalidFen);
+        }
+
+        let mut game = Game {
+            turn,
+            white_king: find_king(&pieces, Color::White),
+            black_king: find_king(&pieces, Color::Black),
+            pieces,
+            state: GameState::InProgress,
+            en_passant,
+            castling_rights,
+            halfmove_clock,
+            fullmove_number,
+            ply: 0,
+            history: vec![],
+            captured: vec![],
+            snapshots: vec![],
+        };
+        game.validate()?;
+        game.state = game.get_game_state();
+        Ok(game)
+    }
+
+    pub fn coords_from_notation(notation: &str) -> Option<(usize, usize)> {
+        notation.parse::<Square>().ok().map(|square| square.into())
+    }
+
+    pub fn notation_from_coords(coords: (usize, usize)) -> Option<String> {
+        coords_to_notation(coords)
+    }
+
+    pub fn validate(&self) -> Result<(), ChessError> {
+        for color in [Color::White, Color::Black] {
+            let king = Piece::new(PieceType::King, color);
+            if self.pieces.values().filter(|piece| **piece == king).count() != 1 {
+                return Err(ChessError::InvalidPosition);
+            }
+        }
+        if
+            self.pieces
+                .iter()
+                .any(|((_, y), piece)| {
+                    piece.piece_type == PieceType::Pawn && (*y == 1 || *y == 8)
+                })
+        {
+            return Err(ChessError::InvalidPosition);
+        }
+        if self.is_check(self.turn.opposite()) {
+            return Err(ChessError::InvalidPosition);
+        }
+        Ok(())
+    }
+
+    pub fn to_fen(&self) -> String {
+        let placement = self.pieces.placement();
+
+        let turn = match self.turn {
+            Color::White => "w",
+            Color::Black => "b",
+        };
+
+        let mut castling = String::new();
+        for (color, castle, letter) in [
+            (Color::White, Castle::KingSide, 'K'),
+            (Color::White, Castle::QueenSide, 'Q'),
+            (Color::Black, Castle::KingSide, 'k'),
+            (Color::Black, Castle::QueenSide, 'q'),
+        ] {
+            if !self.castling_rights.can_castle(color, castle) {
+                continue;
+            }
+            if self.castling_rights.is_standard() {
+                castling.push(letter);
+            } else {
+                // Chess960 rights name the rook's file, as in Shredder-FEN
+                let file = (b'a' + (self.castling_rights.rook_file(castle) as u8) - 1) as char;
+                castling.push(match color {
+                    Color::White => file.to_ascii_uppercase(),
+                    Color::Black => file,
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = self.en_passant
+            .and_then(coords_to_notation)
+            .unwrap_or_else(|| String::from("-"));
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            turn,
+            castling,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    fn next_turn(&mut self) {
+        self.turn = self.turn.opposite();
+    }
+
+    pub fn san(&self, command: &Command) -> Result<String, ChessError> {
+        let check = match self.simulate_move(command) {
+            Ok(mut game) => {
+                game.next_turn();
+                match game.get_game_state() {
+                    GameState::Checkmate(_) => Some(Check::Checkmate),
+                    GameState::Check(_) => Some(Check::Check),
+                    _ => None,
+                }
+            }
+            Err(_) => None,
+        };
+        Command { check, ..command.clone() }.to_notation_on(self)
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let result = match self.result() {
+            Some(GameResult::WhiteWins) => "1-0",
+            Some(GameResult::BlackWins) => "0-1",
+            Some(GameResult::Draw) => "1/2-1/2",
+            None => "*",
+        };
+        let start = self.snapshots.first().unwrap_or(self);
+
+        let mut pgn = String::new();
+        for (tag, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", result),
+        ] {
+            pgn.push_str(&format!("[{} \"{}\"]\n", tag, value));
+        }
+        let start_fen = start.to_fen();
+        if start_fen != Game::new().to_fen() {
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", start_fen));
+        }
+        pgn.push('\n');
+
+        let mut movetext = vec![];
+        for (i, (position, played)) in self.snapshots.iter().zip(&self.history).enumerate() {
+            match position.turn {
+                Color::White => movetext.push(format!("{}.", position.fullmove_number)),
+                // a game starting with black to move needs an ellipsis
+                Color::Black if i == 0 => movetext.push(format!("{}...", position.fullmove_number)),
+                Color::Black => {}
+            }
+            movetext.push(
+                position.san(&played.to_command()).expect("played moves can be written in SAN")
+            );
+        }
+        movetext.push(result.to_string());
+        pgn.push_str(&movetext.join(" "));
+        pgn
+    }
+
+    // the moves played so far in UCI notation, space separated, e.g. "e2e4 e7e5 g1f3"
+    pub fn to_move_string(&self) -> String {
+        self.snapshots
+            .iter()
+            .zip(&self.history)
+            .map(|(position, played)| {
+                played.to_command().to_uci(position).expect("played moves name their squares")
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // replays a to_move_string list from the standard starting position
+    pub fn from_move_string(moves: &str) -> Result<Game, ChessError> {
+        let mut game = Game::new();
+        for (index, uci) in moves.split_whitespace().enumerate() {
+            Command::parse_uci(uci, &game)
+                .ok_or(ChessError::InvalidNotation)
+                .and_then(|command| game.play(&command))
+                .map_err(|error| ChessError::MoveListFailed { index, error: Box::new(error) })?;
+        }
+        Ok(game)
+    }
+
+    pub fn from_pgn(pgn: &str) -> Result<Game, ChessError> {
+        let mut game = Game::new();
+        let mut movetext = String::new();
+        for line in pgn.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                // honour a custom starting position, ignore every other tag
+                if let Some(fen) = line.strip_prefix("[FEN \"") {
+                    game = Game::from_fen(fen.trim_end_matches(']').trim_end_matches('"'))?;
+                }
+            } else if !line.starts_with('%') {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        // drop comments and variations before tokenizing
+        let mut stripped = String::new();
+        let mut depth = 0;
+        let mut in_comment = false;
+        let mut in_line_comment = false;
+        for c in movetext.chars() {
+            match c {
+                '\n' if in_line_comment => {
+                    in_line_comment = false;
+                }
+                _ if in_line_comment => {}
+                '}' if in_comment => {
+                    in_comment = false;
+                }
+                _ if in_comment => {}
+                '{' => {
+                    in_comment = true;
+                }
+                ';' => {
+                    in_line_comment = true;
+                }
+                '(' => {
+                    depth += 1;
+                }
+                ')' => {
+                    depth -= 1;
+                }
+                _ if depth > 0 => {}
+                _ => stripped.push(c),
+            }
+            if depth < 0 {
+                return Err(ChessError::InvalidNotation);
+            }
+        }
+        if depth > 0 || in_comment {
+            return Err(ChessError::InvalidNotation);
+        }
+
+        for token in stripped.split_whitespace() {
+            if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+                break;
+            }
+            // move numbers may be glued to the move, as in "1.e4" or "3...Nf6", but mustn't eat
+            // the zeros of "0-0"
+            let token = match token.trim_start_matches(|c: char| c.is_ascii_digit()) {
+                rest if rest.is_empty() || rest.starts_with('.') => rest.trim_start_matches('.'),
+                _ => token,
+            };
+            if token.is_empty() || token.starts_with('$') {
+                continue;
+            }
+            let command = Command::parse(token).ok_or(ChessError::InvalidNotation)?;
+            game.play(&command)?;
+        }
+        Ok(game)
+    }
+
+    pub fn get_game_state(&self) -> GameState {
+        let has_moves = self.has_any_legal_move(self.turn);
+        let is_check = self.is_check(self.turn);
+
+        if is_check && !has_moves {
+            GameState::Checkmate(self.turn.opposite())
+        } else if is_check && has_moves {
+            GameState::Check(self.turn)
+        } else if !is_check && !has_moves {
+            GameState::Draw(DrawReason::Stalemate)
+        } else {
+            GameState::InProgress
+        }
+    }
+
+    // pieces of the other color that `color` has taken
+    pub fn captured_by(&self, color: Color) -> Vec<Piece> {
+        self.captured
+            .iter()
+            .filter(|piece| piece.color != color)
+            .copied()
+            .collect()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.result().is_some()
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        match self.state {
+            GameState::Checkmate(Color::White) | GameState::Resigned(Color::Black) =>
+                Some(GameResult::WhiteWins),
+            GameState::Checkmate(Color::Black) | GameState::Resigned(Color::White) =>
+                Some(GameResult::BlackWins),
+            GameState::Draw(_) => Some(GameResult::Draw),
+            GameState::InProgress | GameState::Check(_) => None,
+        }
+    }
+
+    pub fn is_check(&self, color_in_check: Color) -> bool {
+        match self.king_position(color_in_check) {
+            Some(king_coords) =>
+                self.is_square_attacked(king_coords, color_in_check.opposite()),
+            None => false,
+        }
+    }
+
+    // squares of the enemy pieces giving check to `color`'s king
+    pub fn checkers(&self, color: Color) -> Vec<(usize, usize)> {
+        let king_coords = match self.king_position(color) {
+            Some(coords) => coords,
+            None => {
+                return vec![];
+            }
+        };
+        self.pieces_of(color.opposite())
+            .filter(|(coords, piece)| piece.can_move(*coords, king_coords, &self.pieces, true))
+            .map(|(coords, _)| coords)
+            .collect()
+    }
+
+    pub fn is_double_check(&self, color: Color) -> bool {
+        self.checkers(color).len() >= 2
+    }
+
+    // squares of `color`'s pieces that can't leave the line between their king and a slider
+    pub fn pinned_pieces(&self, color: Color) -> Vec<(usize, usize)> {
+        let king_coords = match self.king_position(color) {
+            Some(coords) => coords,
+            None => {
+                return vec![];
+            }
+        };
+        let mut pinned = vec![];
+        for &direction in Piece::new(PieceType::Queen, color).get_direction_vectors() {
+            let pinners = if direction.0 == 0 || direction.1 == 0 {
+                [PieceType::Rook, PieceType::Queen]
+            } else {
+                [PieceType::Bishop, PieceType::Queen]
+            };
+            let mut blocker = None;
+            for step in 1..8 {
+                let coords = match next_coords(king_coords, direction, step) {
+                    Some(coords) => coords,
+                    None => {
+                        break;
+                    }
+                };
+                match (self.pieces.get(&coords), blocker) {
+                    (None, _) => {
+                        continue;
+                    }
+                    (Some(piece), None) if piece.color == color => {
+                        blocker = Some(coords);
+                    }
+                    (Some(piece), Some(blocker)) if
+                        piece.color != color &&
+                        pinners.contains(&piece.piece_type)
+                    => {
+                        pinned.push(blocker);
+                        break;
+                    }
+                    _ => {
+                        break;
+                    }
+                }
+            }
+        }
+        pinned.sort_by_key(|coords| square_index(*coords));
+        pinned
+    }
+
+    // occupied squares from a1 to h8, rank by rank
+    pub fn iter_pieces(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        self.pieces.iter().map(|((file, rank), piece)| {
+            (Square { file: *file, rank: *rank }, *piece)
+        })
+    }
+
+    pub fn pieces_of(&self, color: Color) -> impl Iterator<Item = ((usize, usize), Piece)> + '_ {
+        self.pieces
+            .iter()
+            .filter(move |(_, piece)| piece.color == color)
+            .map(|(coords, piece)| (*coords, *piece))
+    }
+
+    pub fn pieces_of_type(
+        &self,
+        color: Color,
+        kind: PieceType
+    ) -> impl Iterator<Item = ((usize, usize), Piece)> + '_ {
+        self.pieces_of(color).filter(move |(_, piece)| piece.piece_type == kind)
+    }
+
+    pub fn piece_at(&self, square: &str) -> Option<Piece> {
+        let coords = Game::coords_from_notation(square)?;
+        self.pieces.get(&coords).copied()
+    }
+
+    pub fn king_position(&self, color: Color) -> Option<(usize, usize)> {
+        let cached = match color {
+            Color::White => self.white_king,
+            Color::Black => self.black_king,
+        };
+        let king = Piece::new(PieceType::King, color);
+        match cached {
+            // `pieces` is public, so fall back to a scan if it was edited directly
+            Some(coords) if self.pieces.get(&coords) == Some(&king) => Some(coords),
+            _ => find_king(&self.pieces, color),
+        }
+    }
+
+    fn set_king_position(&mut self, color: Color, coords: (usize, usize)) {
+        match color {
+            Color::White => {
+                self.white_king = Some(coords);
+            }
+            Color::Black => {
+                self.black_king = Some(coords);
+            }
+        }
+    }
+
+    pub fn is_square_attacked(&self, coords: (usize, usize), by: Color) -> bool {
+        self.pieces_of(by).any(|(piece_coords, piece)| {
+            piece.can_move(piece_coords, coords, &self.pieces, true)
+        })
+    }
+
+    // pseudo-legal moves, so moves that would leave the king in check still count
+    pub fn mobility(&self, color: Color) -> usize {
+        self.pieces_of(color)
+            .map(|(coords, piece)| {
+                piece.get_possible_moves(coords, &self.pieces, self.en_passant).len()
+            })
+            .sum()
+    }
+
+    // every square a piece of `color` attacks, whether empty, defended or held by the enemy
+    pub fn controlled_squares(&self, color: Color) -> HashSet<(usize, usize)> {
+        let mut controlled = HashSet::new();
+        for (coords, piece) in self.pieces_of(color) {
+            for square in SQUARES.iter() {
+                if *square != coords && piece.can_move(coords, *square, &self.pieces, true) {
+                    controlled.insert(*square);
+                }
+            }
+        }
+        controlled
+    }
+
+    pub fn get_all_possible_moves(&self, color: Color) -> Vec<Command> {
+        self.movable_pieces(color)
+            .flat_map(|(coords, piece)| {
+                piece.get_possible_moves(coords, &self.pieces, self.en_passant)
+            })
+            .chain(castle_commands())
+            .filter_map(|command| self.legal_move(command))
+            .collect()
+    }
+
+    // reads the board to fill in everything a move needs beyond its two squares, as a
+    // drag-and-drop UI would; the move still has to be checked when played
+    pub fn build_move(
+        &self,
+        from: (usize, usize),
+        to: (usize, usize),
+        promotion: Option<PieceType>
+    ) -> Option<Command> {
+        let piece = self.pieces.get(&from)?;
+        let command_builder = CommandBuilder::new().piece(piece.piece_type);
+
+        // castling is the king's two-square move, or the king taking its own rook in Chess960
+        let rights = &self.castling_rights;
+        if piece.piece_type == PieceType::King && from.0 == rights.king_file() && from.1 == to.1 {
+            for castle in [Castle::KingSide, Castle::QueenSide] {
+                let is_castle = match (rights.is_standard(), castle) {
+                    (false, _) =>
+                        to.0 == rights.rook_file(castle) &&
+                            self.pieces.get(&to) == Some(&Piece::new(PieceType::Rook, piece.color)),
+                    (true, Castle::KingSide) => to.0 == 7,
+                    (true, Castle::QueenSide) => to.0 == 3,
+                };
+                if is_castle {
+                    return Some(command_builder.castle(Some(castle)).build());
+                }
+            }
+        }
+
+        let target = self.pieces.get(&to);
+        if target.is_some_and(|target| target.color == piece.color) {
+            return None;
+        }
+        let takes =
+            target.is_some() || (piece.piece_type == PieceType::Pawn && self.en_passant == Some(to));
+
+        command_builder
+            .from((Some(from.0), Some(from.1)))
+            .to(to)
+            .takes(takes)
+            .promotion(promotion)
+            .try_build()
+            .ok()
+    }
+
+    // the pieces worth generating moves for: in double check only the king can get out of it
+    fn movable_pieces(&self, color: Color) -> impl Iterator<Item = ((usize, usize), Piece)> + '_ {
+        let king_only = self.is_double_check(color);
+        self.pieces_of(color).filter(move |(_, piece)| {
+            !king_only || piece.piece_type == PieceType::King
+        })
+    }
+
+    // stops at the first legal move instead of generating them all
+    pub fn has_any_legal_move(&self, color: Color) -> bool {
+        self.movable_pieces(color)
+            .flat_map(|(coords, piece)| {
+                piece.get_possible_moves(coords, &self.pieces, self.en_passant)
+            })
+            .chain(castle_commands())
+            .any(|command| self.would_be_legal(&command))
+    }
+
+    // plays the move on a scratch copy of the game and drops it; the board is a fixed array and
+    // the copy carries no history, so checking a move this way never touches the heap
+    pub fn would_be_legal(&self, command: &Command) -> bool {
+        self.simulate_move(command).is_ok()
+    }
+
+    // like would_be_legal, but a move naming its source square is turned away without simulating
+    // it if that square doesn't hold the side to move's piece
+    pub fn is_legal(&self, command: &Command) -> bool {
+        if let (Some(x), Some(y)) = command.from {
+            match self.pieces.get(&(x, y)) {
+                Some(piece) if piece.color == self.turn && piece.piece_type == command.piece => {}
+                _ => {
+                    return false;
+                }
+            }
+        }
+        self.would_be_legal(command)
+    }
+
+    // false for illegal moves as well as quiet ones
+    pub fn move_gives_check(&self, command: &Command) -> bool {
+        match self.simulate_move(command) {
+            Ok(game) => game.is_check(self.turn.opposite()),
+            Err(_) => false,
+        }
+    }
+
+    // every legal move for the side to move, in the same order as get_all_possible_moves
+    pub fn legal_moves_san(&self) -> Vec<String> {
+        self.get_all_possible_moves(self.turn)
+            .iter()
+            .map(|command| self.san(command).expect("generated moves can be written in SAN"))
+            .collect()
+    }
+
+    pub fn legal_moves_from(&self, coords: (usize, usize)) -> Vec<Command> {
+        let piece = match self.pieces.get(&coords) {
+            Some(piece) if piece.color == self.turn => piece,
+            _ => {
+                return vec![];
+            }
+        };
+        let castles = match piece.piece_type {
+            PieceType::King => castle_commands().collect(),
+            _ => vec![],
+        };
+        piece
+            .get_possible_moves(coords, &self.pieces, self.en_passant)
+            .into_iter()
+            .chain(castles)
+            .filter_map(|command| self.legal_move(command))
+            .collect()
+    }
+
+    // keeps the command if it's legal, marking whether it gives check or mate; this simulates the
+    // move itself rather than asking would_be_legal, since both marks need the game it leads to
+    fn legal_move(&self, command: Command) -> Option<Command> {
+        let mut game = self.simulate_move(&command).ok()?;
+        game.next_turn();
+        let check = if !game.is_check(game.turn) {
+            command.check
+        } else if game.has_any_legal_move(game.turn) {
+            Some(Check::Check)
+        } else {
+            Some(Check::Checkmate)
+        };
+        Some(Command { check, ..command })
+    }
+
+    pub fn perft(&self, depth: usize) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.get_all_possible_moves(self.turn);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .iter()
+            .map(|command| self.play_simulated(command).perft(depth - 1))
+            .sum()
+    }
+
+    // keyed by UCI move, e.g. "g1f3", to line up with other engines' divide output
+    pub fn perft_divide(&self, depth: usize) -> Vec<(String, u64)> {
+        if depth == 0 {
+            return vec![];
+        }
+        self.get_all_possible_moves(self.turn)
+            .iter()
+            .map(|command| {
+                (
+                    command.to_uci(self).expect("generated moves name their squares"),
+                    self.play_simulated(command).perft(depth - 1),
+                )
+            })
+            .collect()
+    }
+
+    pub fn is_insufficient_material(&self) -> bool {
+        let minor_pieces = self.pieces
+            .iter()
+            .filter(|(_, piece)| piece.piece_type != PieceType::King)
+            .collect::<Vec<_>>();
+        match minor_pieces.as_slice() {
+            [] => true,
+            [(_, piece)] =>
+                piece.piece_type == PieceType::Bishop || piece.piece_type == PieceType::Knight,
+            [(first_coords, first), (second_coords, second)] => {
+                // bishops confined to the same square color can never deliver mate
+                first.piece_type == PieceType::Bishop &&
+                    second.piece_type == PieceType::Bishop &&
+                    first.color != second.color &&
+                    (first_coords.0 + first_coords.1) % 2 == (second_coords.0 + second_coords.1) % 2
+            }
+            _ => false,
+        }
+    }
+
+    // the FEN without its move counters, so transpositions compare equal
+    pub fn position_key(&self) -> String {
+        self.to_fen().split(' ').take(4).collect::<Vec<_>>().join(" ")
+    }
+
+    pub fn is_fifty_move_rule(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    pub fn is_seventy_five_move_rule(&self) -> bool {
+        self.halfmove_clock >= 150
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetitions() >= 2
+    }
+
+    pub fn is_fivefold_repetition(&self) -> bool {
+        self.repetitions() >= 4
+    }
+
+    // how many times the current position occurred before
+    fn repetitions(&self) -> usize {
+        let hash = self.zobrist_hash();
+        // positions before the last capture or pawn move can't come back
+        self.snapshots
+            .iter()
+            .rev()
+            .take(self.halfmove_clock)
+            .filter(|snapshot| snapshot.zobrist_hash() == hash)
+            .count()
+    }
+
+    pub fn zobrist_hash(&self) -> u64 {
+        let mut hash = 0;
+        for (coords, piece) in self.pieces.iter() {
+            hash ^= ZOBRIST_KEYS.pieces[zobrist_piece_index(piece)][
+                square_index(*coords).unwrap()
+            ];
+        }
+        if self.turn == Color::Black {
+            hash ^= ZOBRIST_KEYS.black_to_move;
+        }
+        for (i, right) in [
+            self.castling_rights.white_king_side,
+            self.castling_rights.white_queen_side,
+            self.castling_rights.black_king_side,
+            self.castling_rights.black_queen_side,
+        ]
+            .into_iter()
+            .enumerate() {
+            if right {
+                hash ^= ZOBRIST_KEYS.castling[i];
+            }
+        }
+        if let Some((x, y)) = self.en_passant {
+            // only hash the target if a pawn could actually take en passant
+            let pushed_pawn_row = match self.turn {
+                Color::White => y - 1,
+                Color::Black => y + 1,
+            };
+            let can_capture = [x.checked_sub(1), x.checked_add(1)]
+                .into_iter()
+                .flatten()
+                .any(|col| {
+                    self.pieces.get(&(col, pushed_pawn_row)) ==
+                        Some(&Piece::new(PieceType::Pawn, self.turn))
+                });
+            if can_capture {
+                hash ^= ZOBRIST_KEYS.en_passant[x - 1];
+            }
+        }
+        hash
+    }
+
+    // the same position with the board flipped top to bottom and the colors swapped
+    pub fn mirrored(&self) -> Game {
+        let mirror = |(x, y): (usize, usize)| (x, 9 - y);
+        let pieces = self.pieces
+            .iter()
+            .map(|(coords, piece)| {
+                (mirror(*coords), Piece::new(piece.piece_type, piece.color.opposite()))
+            })
+            .collect::<Board>();
+        let rights = self.castling_rights;
+        Game {
+            turn: self.turn.opposite(),
+            state: match self.state {
+                GameState::Checkmate(color) => GameState::Checkmate(color.opposite()),
+                GameState::Check(color) => GameState::Check(color.opposite()),
+                GameState::Resigned(color) => GameState::Resigned(color.opposite()),
+                state => state,
+            },
+            en_passant: self.en_passant.map(mirror),
+            castling_rights: CastlingRights {
+                white_king_side: rights.black_king_side,
+                white_queen_side: rights.black_queen_side,
+                black_king_side: rights.white_king_side,
+                black_queen_side: rights.white_queen_side,
+                ..rights
+            },
+            white_king: self.black_king.map(mirror),
+            black_king: self.white_king.map(mirror),
+            pieces,
+            ..self.without_history()
+        }
+    }
+
+    // material only, in centipawns, from the point of view of the side to move
+    pub fn evaluate(&self) -> i32 {
+        self.pieces
+            .values()
+            .map(|piece| {
+                let value = (piece.piece_type.value() as i32) * 100;
+                if piece.color == self.turn { value } else { -value }
+            })
+            .sum()
+    }
+
+    // the pieces on the board regardless of where they stand, white's then black's, most
+    // valuable first, as in "KRPvKR"
+    pub fn material_signature(&self) -> String {
+        let side = |color: Color| {
+            [
+                PieceType::King,
+                PieceType::Queen,
+                PieceType::Rook,
+                PieceType::Bishop,
+                PieceType::Knight,
+                PieceType::Pawn,
+            ]
+                .into_iter()
+                .flat_map(|kind| self.pieces_of_type(color, kind))
+                .map(|(_, piece)| piece.letter())
+                .collect::<String>()
+        };
+        format!("{}v{}", side(Color::White), side(Color::Black))
+    }
+
+    // white minus black, in pawns
+    pub fn material_balance(&self) -> i32 {
+        self.pieces
+            .values()
+            .map(|piece| {
+                let value = piece.piece_type.value() as i32;
+                match piece.color {
+                    Color::White => value,
+                    Color::Black => -value,
+                }
+            })
+            .sum()
+    }
+
+    // legal moves come out in board order, so the same seed always picks the same move
+    pub fn random_move(&self, rng: &mut impl Rng) -> Option<Command> {
+        let mut moves = self.get_all_possible_moves(self.turn);
+        if moves.is_empty() {
+            return None;
+        }
+        // scaling rather than taking a remainder keeps the pick unbiased enough for any move count
+        let index = (((rng.next_u64() as u128) * (moves.len() as u128)) >> 64) as usize;
+        Some(moves.swap_remove(index))
+    }
+
+    pub fn random_move_seeded(&self, seed: u64) -> Option<Command> {
+        self.random_move(&mut SplitMix64::new(seed))
+    }
+
+    pub fn best_move(&self, depth: usize) -> Option<Command> {
+        let mut best = None;
+        let mut alpha = -MATE_SCORE - (depth as i32) - 1;
+        for command in self.get_all_possible_moves(self.turn) {
+            let score = -self.play_simulated(&command).negamax(
+                depth.saturating_sub(1),
+                -MATE_SCORE - (depth as i32) - 1,
+                -alpha
+            );
+            if best.is_none() || score > alpha {
+                alpha = score;
+                best = Some(command);
+            }
+        }
+        best
+    }
+
+    // the search's verdict for the side to move, in pawns like "+1.50" or as "Mate in 3"
+    pub fn evaluation_text(&self, depth: usize) -> String {
+        let bound = MATE_SCORE + (depth as i32) + 1;
+        let score = self.negamax(depth, -bound, bound);
+        if score.abs() < MATE_SCORE {
+            return format!("{:+.2}", (score as f64) / 100.0);
+        }
+        // negamax scores a mate by the depth it had left, so the plies until mate fall out of it
+        let plies = depth - ((score.abs() - MATE_SCORE) as usize);
+        if score > 0 {
+            format!("Mate in {}", plies.div_ceil(2))
+        } else if plies == 0 {
+            String::from("Checkmate")
+        } else {
+            format!("Mated in {}", plies / 2)
+        }
+    }
+
+    fn negamax(&self, depth: usize, mut alpha: i32, beta: i32) -> i32 {
+        let moves = self.get_all_possible_moves(self.turn);
+        if moves.is_empty() {
+            // mates closer to the root score further from zero
+            return if self.is_check(self.turn) { -MATE_SCORE - (depth as i32) } else { 0 };
+        }
+        if self.is_fifty_move_rule() || self.is_insufficient_material() {
+            return 0;
+        }
+        if depth == 0 {
+            return self.evaluate();
+        }
+        for command in moves {
+            let score = -self.play_simulated(&command).negamax(depth - 1, -beta, -alpha);
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    // applies an already-validated move without recording history
+    fn play_simulated(&self, command: &Command) -> Game {
+        let mut game = self.simulate_move(command).expect("generated moves are legal");
+        game.next_turn();
+        game
+    }
+}
+
+// a source of random bits for random_move
+pub trait Rng {
+    fn next_u64(&mut self) -> u64;
+}
+
+// a small, fast generator that is fully determined by its seed
+#[derive(Clone, Copy, Debug)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl Rng for SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+struct ZobristKeys {
+    pieces: [[u64; 64]; 12],
+    black_to_move: u64,
+    castling: [u64; 4],
+    en_passant: [u64; 8],
+}
+
+lazy_static! {
+    static ref ZOBRIST_KEYS: ZobristKeys = {
+        // a fixed seed, so hashes are stable across runs
+        let mut rng = SplitMix64::new(0x2545_f491_4f6c_dd1d);
+        let mut next = || rng.next_u64();
+        let mut keys = ZobristKeys {
+            pieces: [[0; 64]; 12],
+            black_to_move: 0,
+            castling: [0; 4],
+            en_passant: [0; 8],
+        };
+        for piece_keys in keys.pieces.iter_mut() {
+            for key in piece_keys.iter_mut() {
+                *key = next();
+            }
+        }
+        keys.black_to_move = next();
+        for key in keys.castling.iter_mut() {
+            *key = next();
+        }
+        for key in keys.en_passant.iter_mut() {
+            *key = next();
+        }
+        keys
+    };
+}
+
+fn zobrist_piece_index(piece: &Piece) -> usize {
+    let piece_type = match piece.piece_type {
+        PieceType::King => 0,
+        PieceType::Queen => 1,
+        PieceType::Rook => 2,
+        PieceType::Bishop => 3,
+        PieceType::Knight => 4,
+        PieceType::Pawn => 5,
+    };
+    match piece.color {
+        Color::White => piece_type,
+        Color::Black => piece_type + 6,
+    }
+}
+
+fn find_king(pieces: &Board, color: Color) -> Option<(usize, usize)> {
+    pieces
+        .iter()
+        .find(|(_, piece)| piece.piece_type == PieceType::King && piece.color == color)
+        .map(|(coords, _)| *coords)
+}
+
+fn square_index(coords: (usize, usize)) -> Option<usize> {
+    let (x, y) = coords;
+    if !(1..=8).contains(&x) || !(1..=8).contains(&y) {
+        return None;
+    }
+    Some((y - 1) * 8 + (x - 1))
+}
+
+fn notation_to_coords(notation: &str) -> Option<(usize, usize)> {
+    match notation.as_bytes() {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] =>
+            Some(((file - b'a' + 1) as usize, (rank - b'1' + 1) as usize)),
+        _ => None,
+    }
+}
+
+fn letter_to_column_index(letter: char) -> usize {
+    let letter = letter.to_ascii_lowercase();
+    if letter < 'a' || letter > 'h' {
+        panic!("How did we get here? I thought we checked this already.");
+    }
+    (letter as usize) - ('a' as usize) + 1
+}
+
+fn column_index_to_letter(col: usize) -> Option<char> {
+    if !(1..=8).contains(&col) {
+        return None;
+    }
+    Some((b'a' + (col as u8) - 1) as char)
+}
+
+// None for anything off the board, rather than whatever characters the arithmetic lands on
+fn coords_to_notation(coords: (usize, usize)) -> Option<String> {
+    square_index(coords)?;
+    let x = (coords.0 as u8) + b'a' - 1;
+    let y = (coords.1 as u8) + b'1' - 1;
+    Some(format!("{}{}", x as char, y as char))
+}
+
+fn coords_match_from(coords: (usize, usize), from: (Option<usize>, Option<usize>)) -> bool {
+    match from {
+        (Some(x), None) => coords.0 == x,
+        (None, Some(y)) => coords.1 == y,
+        (Some(x), Some(y)) => coords.0 == x && coords.1 == y,
+        _ => true,
+    }
+}
+
+fn next_coords(
+    origin: (usize, usize),
+    direction: (isize, isize),
+    step: isize
+) -> Option<(usize, usize)> {
+    let (x, y) = origin;
+    let (direction_x, direction_y) = direction;
+    let x = (x as isize) + direction_x * step;
+    let y = (y as isize) + direction_y * step;
+    if x < 1 || x > 8 || y < 1 || y > 8 {
+        return None;
+    }
+    Some((x as usize, y as usize))
+}
+
+// the squares strictly between two squares on a shared rank, file or diagonal, or none at all
+// if they don't share one
+pub fn squares_between(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    between(from, to).collect()
+}
+
+// like squares_between, but lazy so path checks during move simulation don't allocate
+fn between(from: (usize, usize), to: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    let (distance_x, distance_y) = (from.0.abs_diff(to.0), from.1.abs_diff(to.1));
+    let aligned = distance_x == 0 || distance_y == 0 || distance_x == distance_y;
+    let steps = if aligned { distance_x.max(distance_y) } else { 0 };
+    let step = (direction(from.0, to.0), direction(from.1, to.1));
+    (1..steps).filter_map(move |i| next_coords(from, step, i as isize))
+}
+
+// -1, 0 or 1: the single step along one axis that heads from `from` towards `to`
+fn direction(from: usize, to: usize) -> isize {
+    ((to as isize) - (from as isize)).signum()
+}
+
+fn pawn_move(y_coord: usize, step: isize, color: Color) -> Option<usize> {
+    let direction = if color == Color::White { 1 } else { -1 };
+    let new_y = (y_coord as isize) + step * direction;
+
+    if new_y < 1 || new_y > 8 {
+        None
+    } else {
+        Some(new_y as usize)
+    }
+}
+
+fn castle_commands() -> impl Iterator<Item = Command> {
+    [Castle::KingSide, Castle::QueenSide]
+        .into_iter()
+        .map(|castle| CommandBuilder::new().piece(PieceType::King).castle(Some(castle)).build())
+}
+
+fn pawn_commands(command_builder: CommandBuilder, to: (usize, usize)) -> Vec<Command> {
+    let command_builder = command_builder.to(to);
+    if to.1 == 1 || to.1 == 8 {
+        [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight]
+            .into_iter()
+            .map(|promotion| command_builder.promotion(Some(promotion)).build())
+            .collect()
+    } else {
+        vec![command_builder.build()]
+    }
+}
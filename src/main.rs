@@ -1,8 +1,14 @@
-use chess::{ Command, Game, GameState };
+use chess::{ Command, DrawReason, Game, GameState };
 
 fn main() {
+    if std::env::args().any(|arg| arg == "--uci") {
+        uci_loop();
+        return;
+    }
     let mut chess = Game::new();
     let mut input = String::new();
+    println!("Enter moves in algebraic notation (e.g. e4, Nf3, O-O), or \"quit\" to exit\n");
+    println!("{}", chess);
     loop {
         match chess.state {
             GameState::Checkmate(color) => {
@@ -12,25 +18,41 @@ fn main() {
             GameState::Check(color) => {
                 println!("Check! {:?} is in check! Make a move", &color);
             }
-            GameState::Stalemate => {
+            GameState::Draw(DrawReason::Stalemate) => {
                 println!("Stalemate! It's a draw!");
                 break;
             }
+            GameState::Draw(reason) => {
+                println!("It's a draw! ({:?})", reason);
+                break;
+            }
+            GameState::Resigned(color) => {
+                println!("{:?} resigned! {:?} wins!", &color, color.opposite());
+                break;
+            }
             GameState::InProgress => {
                 println!("Make a move, {:?}", chess.turn);
             }
         }
-        println!(
-            "Possible moves: {:?}",
-            chess
-                .get_all_possible_moves(chess.turn)
-                .into_iter()
-                .map(|c| c.to_notation())
-                .collect::<Vec<String>>()
-        );
+        if chess.can_claim_draw() {
+            println!("Enter \"claim\" to claim a draw");
+        }
+        println!("Possible moves: {:?}", chess.legal_moves_san());
         match std::io::stdin().read_line(&mut input) {
+            // end of input
+            Ok(0) => {
+                break;
+            }
+            Ok(_) if input.trim() == "quit" => {
+                break;
+            }
+            Ok(_) if input.trim() == "claim" => {
+                if let Err(e) = chess.claim_draw() {
+                    println!("{}", e);
+                }
+            }
             Ok(_) => {
-                if let Some(command) = Command::parse(&input.trim()) {
+                if let Some(command) = Command::parse(input.trim()) {
                     let result = chess.play(&command);
                     match result {
                         Ok(_) => {
@@ -53,11 +75,100 @@ fn main() {
     }
 }
 
+fn uci_loop() {
+    let mut game = Game::new();
+    let mut input = String::new();
+    loop {
+        input.clear();
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => {
+                break;
+            }
+            Ok(_) if input.trim() == "quit" => {
+                break;
+            }
+            Ok(_) => {
+                for response in uci_response(&mut game, input.trim()) {
+                    println!("{}", response);
+                }
+            }
+        }
+    }
+}
+
+fn uci_response(game: &mut Game, line: &str) -> Vec<String> {
+    let mut tokens = line.split_whitespace();
+    match tokens.next() {
+        Some("uci") => vec!["id name chess".to_string(), "uciok".to_string()],
+        Some("isready") => vec!["readyok".to_string()],
+        Some("ucinewgame") => {
+            *game = Game::new();
+            vec![]
+        }
+        Some("position") => {
+            let position = match tokens.next() {
+                Some("startpos") => Some(Game::new()),
+                Some("fen") => {
+                    let fen = tokens
+                        .by_ref()
+                        .take_while(|token| *token != "moves")
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    Game::from_fen(&fen).ok()
+                }
+                _ => None,
+            };
+            if let Some(mut position) = position {
+                for token in tokens.skip_while(|token| *token == "moves") {
+                    match Command::parse_uci(token, &position) {
+                        Some(command) if position.play(&command).is_ok() => {}
+                        _ => {
+                            break;
+                        }
+                    }
+                }
+                *game = position;
+            }
+            vec![]
+        }
+        Some("go") => {
+            // searches as deep as asked, or three plies when no depth is given
+            let depth = tokens
+                .skip_while(|token| *token != "depth")
+                .nth(1)
+                .and_then(|depth| depth.parse().ok())
+                .unwrap_or(3);
+            match game.best_move(depth).and_then(|command| command.to_uci(game)) {
+                Some(uci) => vec![format!("bestmove {}", uci)],
+                None => vec!["bestmove 0000".to_string()],
+            }
+        }
+        _ => vec![],
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::alloc::{ GlobalAlloc, Layout, System };
+    use std::cell::Cell;
+    use std::collections::{ HashMap, HashSet };
 
-    use chess::{ ChessError, Color, GameState, Piece, PieceType, Castle, CommandBuilder, Check };
+    use chess::{
+        ChessError,
+        Color,
+        GameState,
+        GameResult,
+        Piece,
+        PieceType,
+        Castle,
+        CommandBuilder,
+        Square,
+        Check,
+        Annotation,
+        squares_between,
+        SplitMix64,
+        Move,
+    };
 
     use super::*;
 
@@ -167,16 +278,15 @@ mod tests {
 
     #[test]
     fn all_pieces_can_check() {
-        let mut chess = Game {
-            turn: Color::White,
-            state: GameState::InProgress,
-            pieces: vec![
+        let mut chess = Game::from(
+            vec![
                 ((2, 2), Piece { piece_type: PieceType::King, color: Color::White }),
                 ((2, 4), Piece { piece_type: PieceType::King, color: Color::Black })
             ]
                 .into_iter()
                 .collect(),
-        };
+            Color::White
+        );
         assert!(!chess.is_check(Color::White));
         assert!(!chess.is_check(Color::Black));
 
@@ -427,4 +537,2339 @@ mod tests {
             2
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn queen_moves_along_lines_only() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((4, 1), Piece { piece_type: PieceType::Queen, color: Color::White }),
+            ((5, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((5, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let chess = Game::from(pieces, Color::White);
+
+        // knight-like and arbitrary jumps are rejected
+        for target in ["Qe3", "Qc3", "Qa5", "Qh2"] {
+            let result = chess.simulate_move(&Command::parse(target).unwrap());
+            assert_eq!(result.err(), Some(ChessError::InvalidMove));
+        }
+
+        // rank, file and diagonal moves still succeed
+        for (target, coords) in [
+            ("Qa1", (1, 1)),
+            ("Qd7", (4, 7)),
+            ("Qh5", (8, 5)),
+            ("Qa4", (1, 4)),
+        ] {
+            let game = chess.simulate_move(&Command::parse(target).unwrap()).unwrap();
+            assert_eq!(game.pieces.get(&(4, 1)), None);
+            assert_eq!(
+                game.pieces.get(&coords),
+                Some(&(Piece { piece_type: PieceType::Queen, color: Color::White }))
+            );
+        }
+    }
+
+    #[test]
+    fn castle_notation_round_trips() {
+        for notation in ["O-O", "O-O+", "O-O-O", "O-O-O+", "O-O-O#"] {
+            let command = Command::parse(notation).unwrap();
+            assert_eq!(command.to_notation().unwrap(), notation);
+        }
+    }
+
+    #[test]
+    fn castling_accepts_zeros() {
+        let spellings = [("0-0", "O-O"), ("0-0-0", "O-O-O"), ("0-0+", "O-O+"), ("0-0-0#", "O-O-O#")];
+        for (zeros, letters) in spellings {
+            let command = Command::parse(zeros).unwrap();
+            let expected = Command::parse(letters).unwrap();
+            assert_eq!(command.castle, expected.castle);
+            assert_eq!(command.check, expected.check);
+            assert_eq!(command.piece, PieceType::King);
+            assert_eq!(command.to_notation().unwrap(), letters);
+        }
+        assert!(Command::parse("0-0-").is_none());
+
+        let chess = Game::from_pgn("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 Nf6 *").unwrap();
+        assert_eq!(chess.king_position(Color::White), Some((7, 1)));
+    }
+
+    #[test]
+    fn promotion_notation_round_trips() {
+        let command = Command::parse("exd8=N#").unwrap();
+        assert_eq!(command.from, (Some(5), None));
+        assert_eq!(command.to, (4, 8));
+        assert!(command.takes);
+        assert_eq!(command.promotion, Some(PieceType::Knight));
+        assert_eq!(command.check, Some(Check::Checkmate));
+        assert_eq!(command.to_notation().unwrap(), "exd8=N#");
+
+        let command = Command::parse("e8=Q+").unwrap();
+        assert_eq!(command.promotion, Some(PieceType::Queen));
+        assert_eq!(command.check, Some(Check::Check));
+        assert_eq!(command.to_notation().unwrap(), "e8=Q+");
+
+        // the suffix is the one the promoted piece earns on the board
+        let chess = Game::from_fen("k7/4P3/8/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(chess.san(&Command::parse("e8=Q").unwrap()).unwrap(), "e8=Q+");
+        let chess = Game::from_fen("rnbr4/pkp1P3/pp6/8/8/8/8/7K w - - 0 1").unwrap();
+        assert_eq!(chess.san(&Command::parse("exd8=N").unwrap()).unwrap(), "exd8=N#");
+        assert_eq!(chess.san(&Command::parse("exd8=Q").unwrap()).unwrap(), "exd8=Q");
+    }
+
+    #[test]
+    fn annotation_glyphs_round_trip() {
+        let command = Command::parse("Nf3!").unwrap();
+        assert_eq!(command.annotation, Some(Annotation::Good));
+        assert_eq!(command.to_notation().unwrap(), "Nf3!");
+
+        let command = Command::parse("Qh5??").unwrap();
+        assert_eq!(command.annotation, Some(Annotation::Blunder));
+        assert_eq!(command.to_notation().unwrap(), "Qh5??");
+
+        let command = Command::parse("exd8=Q+!?").unwrap();
+        assert_eq!(command.check, Some(Check::Check));
+        assert_eq!(command.annotation, Some(Annotation::Interesting));
+        assert_eq!(command.to_notation().unwrap(), "exd8=Q+!?");
+
+        let command = Command::parse("O-O?!").unwrap();
+        assert_eq!(command.annotation, Some(Annotation::Dubious));
+        assert_eq!(command.to_notation().unwrap(), "O-O?!");
+
+        assert_eq!(Command::parse("e4!!").unwrap().annotation, Some(Annotation::Brilliant));
+        assert_eq!(Command::parse("e4?").unwrap().annotation, Some(Annotation::Mistake));
+        assert_eq!(Command::parse("e4").unwrap().annotation, None);
+        assert!(Command::parse("e4!!!").is_none());
+
+        // annotated games still replay
+        let chess = Game::from_pgn("1. e4! e5 2. Qh5?! Nc6 3. Bc4 Nf6?? 4. Qxf7# 1-0").unwrap();
+        assert_eq!(chess.get_game_state(), GameState::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn pieces_convert_to_and_from_fen_chars() {
+        assert_eq!(Piece::try_from('n'), Ok(Piece::new(PieceType::Knight, Color::Black)));
+        assert_eq!(Piece::try_from('Q'), Ok(Piece::new(PieceType::Queen, Color::White)));
+        assert_eq!(Piece::try_from('x'), Err(ChessError::InvalidFen));
+
+        for c in "KQRBNPkqrbnp".chars() {
+            assert_eq!(Piece::try_from(c).unwrap().fen_char(), c);
+        }
+    }
+
+    #[test]
+    fn squares_between_follow_lines() {
+        // a1 to e1 along the rank
+        assert_eq!(squares_between((1, 1), (5, 1)), vec![(2, 1), (3, 1), (4, 1)]);
+        // h8 to e5 along the diagonal, in order from the first square
+        assert_eq!(squares_between((8, 8), (5, 5)), vec![(7, 7), (6, 6)]);
+        // e2 to e4 along the file
+        assert_eq!(squares_between((5, 2), (5, 4)), vec![(5, 3)]);
+        // neighbours and unaligned squares have nothing between them
+        assert!(squares_between((4, 4), (5, 5)).is_empty());
+        assert!(squares_between((2, 1), (3, 3)).is_empty());
+        assert!(squares_between((1, 1), (8, 2)).is_empty());
+    }
+
+    #[test]
+    fn pawn_cannot_jump_over_blocker() {
+        let mut chess = Game::new();
+        chess.pieces.insert((5, 3), Piece { piece_type: PieceType::Knight, color: Color::Black });
+
+        let result = chess.simulate_move(&Command::parse("e4").unwrap());
+        assert_eq!(result.err(), Some(ChessError::PathBlocked));
+        assert!(
+            !chess
+                .get_all_possible_moves(Color::White)
+                .iter()
+                .any(|m| m.to == (5, 4))
+        );
+    }
+
+    #[test]
+    fn en_passant_captures() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((5, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((4, 5), Piece { piece_type: PieceType::Pawn, color: Color::White }),
+            ((5, 5), Piece { piece_type: PieceType::Pawn, color: Color::Black }),
+            ((5, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let mut chess = Game::from(pieces, Color::White);
+        chess.en_passant = Some((5, 6));
+
+        let moves = chess.get_all_possible_moves(Color::White);
+        assert!(moves.iter().any(|m| m.piece == PieceType::Pawn && m.takes && m.to == (5, 6)));
+
+        chess.play(&Command::parse("dxe6").unwrap()).unwrap();
+        assert_eq!(
+            chess.pieces.get(&(5, 6)),
+            Some(&(Piece { piece_type: PieceType::Pawn, color: Color::White }))
+        );
+        assert_eq!(chess.pieces.get(&(5, 5)), None);
+        assert_eq!(chess.pieces.get(&(4, 5)), None);
+
+        // black captures towards the first rank, so the taken pawn sits above the target
+        let mut chess = Game::from_fen("4k3/8/8/8/3Pp3/8/8/4K3 b - d3 0 1").unwrap();
+        chess.play(&Command::parse("exd3").unwrap()).unwrap();
+        assert_eq!(
+            chess.pieces.get(&(4, 3)),
+            Some(&(Piece { piece_type: PieceType::Pawn, color: Color::Black }))
+        );
+        assert_eq!(chess.pieces.get(&(4, 4)), None);
+        assert_eq!(chess.pieces.get(&(5, 4)), None);
+    }
+
+    #[test]
+    fn en_passant_checks_are_annotated() {
+        let en_passant = |fen: &str| {
+            let chess = Game::from_fen(fen).unwrap();
+            chess
+                .get_all_possible_moves(chess.turn)
+                .into_iter()
+                .find(|m| m.piece == PieceType::Pawn && m.takes)
+                .unwrap()
+        };
+        // the capturing pawn gives check itself
+        let command = en_passant("8/4k3/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(command.check, Some(Check::Check));
+        assert_eq!(command.to_notation().unwrap(), "exd6+");
+
+        // both pawns leave the rank, uncovering the rook
+        let command = en_passant("8/8/8/R2pP2k/8/8/8/4K3 w - d6 0 1");
+        assert_eq!(command.check, Some(Check::Check));
+        assert_eq!(command.to_notation().unwrap(), "exd6+");
+
+        // only the captured pawn leaves the diagonal, uncovering the bishop
+        let fen = "8/1k6/8/3pP3/8/5B2/8/4K3 w - d6 0 1";
+        let command = en_passant(fen);
+        assert_eq!(command.check, Some(Check::Check));
+        assert_eq!(command.to_notation().unwrap(), "exd6+");
+        let mut chess = Game::from_fen(fen).unwrap();
+        assert_eq!(chess.san(&Command::parse("exd6").unwrap()).unwrap(), "exd6+");
+        chess.play(&command).unwrap();
+        assert_eq!(chess.state, GameState::Check(Color::Black));
+
+        // castling is simulated with the rights and rook of the real game too
+        let chess = Game::from_fen("5k2/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let castle = chess
+            .get_all_possible_moves(Color::White)
+            .into_iter()
+            .find(|m| m.castle.is_some())
+            .unwrap();
+        assert_eq!(castle.to_notation().unwrap(), "O-O+");
+    }
+
+    #[test]
+    fn en_passant_cannot_expose_king() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((1, 5), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((4, 5), Piece { piece_type: PieceType::Pawn, color: Color::White }),
+            ((5, 5), Piece { piece_type: PieceType::Pawn, color: Color::Black }),
+            ((8, 5), Piece { piece_type: PieceType::Rook, color: Color::Black }),
+            ((5, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let mut chess = Game::from(pieces, Color::White);
+        chess.en_passant = Some((5, 6));
+
+        let moves = chess.get_all_possible_moves(Color::White);
+        assert!(!moves.iter().any(|m| m.piece == PieceType::Pawn && m.takes));
+        assert_eq!(
+            chess.play(&Command::parse("dxe6").unwrap()),
+            Err(ChessError::InCheck)
+        );
+    }
+
+    #[test]
+    fn en_passant_target_is_tracked() {
+        let mut chess = Game::new();
+        assert_eq!(chess.en_passant, None);
+
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_eq!(chess.en_passant, Some((5, 3)));
+
+        chess.play(&Command::parse("Nf6").unwrap()).unwrap();
+        assert_eq!(chess.en_passant, None);
+
+        chess.play(&Command::parse("e5").unwrap()).unwrap();
+        assert_eq!(chess.en_passant, None);
+
+        chess.play(&Command::parse("d5").unwrap()).unwrap();
+        assert_eq!(chess.en_passant, Some((4, 6)));
+
+        chess.play(&Command::parse("exd6").unwrap()).unwrap();
+        assert_eq!(chess.en_passant, None);
+        assert_eq!(chess.pieces.get(&(4, 5)), None);
+    }
+
+    #[test]
+    fn pawns_promote() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((1, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((5, 7), Piece { piece_type: PieceType::Pawn, color: Color::White }),
+            ((2, 7), Piece { piece_type: PieceType::Pawn, color: Color::White }),
+            ((8, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let chess = Game::from(pieces, Color::White);
+
+        let command = Command::parse("e8=N").unwrap();
+        assert_eq!(command.promotion, Some(PieceType::Knight));
+        assert_eq!(command.to_notation().unwrap(), "e8=N");
+        let game = chess.simulate_move(&command).unwrap();
+        assert_eq!(
+            game.pieces.get(&(5, 8)),
+            Some(&(Piece { piece_type: PieceType::Knight, color: Color::White }))
+        );
+
+        let game = chess.simulate_move(&Command::parse("e8=Q").unwrap()).unwrap();
+        assert_eq!(
+            game.pieces.get(&(5, 8)),
+            Some(&(Piece { piece_type: PieceType::Queen, color: Color::White }))
+        );
+
+        // without an explicit piece the pawn becomes a queen
+        let game = chess.simulate_move(&Command::parse("b8").unwrap()).unwrap();
+        assert_eq!(
+            game.pieces.get(&(2, 8)),
+            Some(&(Piece { piece_type: PieceType::Queen, color: Color::White }))
+        );
+
+        assert!(Command::parse("Ne8=Q").is_none());
+        assert!(Command::parse("e8=K").is_none());
+    }
+
+    #[test]
+    fn promotion_moves_are_generated() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((1, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((5, 7), Piece { piece_type: PieceType::Pawn, color: Color::White }),
+            ((8, 6), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let mut chess = Game::from(pieces, Color::White);
+
+        let pawn_moves = chess
+            .get_all_possible_moves(Color::White)
+            .into_iter()
+            .filter(|m| m.piece == PieceType::Pawn)
+            .collect::<Vec<_>>();
+        assert_eq!(pawn_moves.len(), 4);
+        for promotion in [PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight] {
+            assert!(pawn_moves.iter().any(|m| m.to == (5, 8) && m.promotion == Some(promotion)));
+        }
+
+        // captures onto the back rank fan out as well
+        chess.pieces.insert((4, 8), Piece { piece_type: PieceType::Rook, color: Color::Black });
+        let pawn_moves = chess
+            .get_all_possible_moves(Color::White)
+            .into_iter()
+            .filter(|m| m.piece == PieceType::Pawn)
+            .collect::<Vec<_>>();
+        assert_eq!(pawn_moves.len(), 8);
+        assert_eq!(
+            pawn_moves
+                .iter()
+                .filter(|m| m.takes && m.promotion.is_some())
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn state_resets_after_check() {
+        let mut chess = Game::new();
+        for command in ["e4", "f5", "Qh5+"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert_eq!(chess.state, GameState::Check(Color::Black));
+
+        chess.play(&Command::parse("g6").unwrap()).unwrap();
+        assert_eq!(chess.state, GameState::InProgress);
+    }
+
+    #[test]
+    fn each_escape_from_check_prevents_mate() {
+        // the king steps aside
+        let chess = Game::from_fen("4k3/8/8/8/8/8/8/K3R3 b - - 0 1").unwrap();
+        assert_eq!(chess.get_game_state(), GameState::Check(Color::Black));
+        assert!(chess.would_be_legal(&Command::parse("Kd7").unwrap()));
+
+        // only the knight taking the rook saves the king
+        let chess = Game::from_fen("4R2k/6pp/3n4/8/8/8/8/K7 b - - 0 1").unwrap();
+        assert_eq!(chess.get_game_state(), GameState::Check(Color::Black));
+        assert_eq!(chess.legal_moves_san(), vec!["Nxe8"]);
+
+        // only the rook dropping back to block saves the king
+        let chess = Game::from_fen("4R2k/6pp/8/8/8/8/5r2/K7 b - - 0 1").unwrap();
+        assert_eq!(chess.get_game_state(), GameState::Check(Color::Black));
+        assert_eq!(chess.legal_moves_san(), vec!["Rf8"]);
+
+        // with nothing to interpose, the same back rank is mate
+        let chess = Game::from_fen("4R2k/6pp/8/8/8/8/8/K7 b - - 0 1").unwrap();
+        assert_eq!(chess.get_game_state(), GameState::Checkmate(Color::White));
+
+        let mut chess = Game::from_fen("7k/6pp/8/8/8/8/5r2/K3R3 w - - 0 1").unwrap();
+        assert_eq!(chess.san(&Command::parse("Re8").unwrap()).unwrap(), "Re8+");
+        chess.play(&Command::parse("Re8").unwrap()).unwrap();
+        assert_eq!(chess.state, GameState::Check(Color::Black));
+    }
+
+    #[test]
+    fn ambiguous_moves_are_rejected() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((5, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((4, 2), Piece { piece_type: PieceType::Knight, color: Color::White }),
+            ((6, 2), Piece { piece_type: PieceType::Knight, color: Color::White }),
+            ((5, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let mut chess = Game::from(pieces, Color::White);
+
+        assert_eq!(
+            chess.simulate_move(&Command::parse("Ne4").unwrap()).err(),
+            Some(ChessError::AmbiguousMove)
+        );
+
+        chess.play(&Command::parse("Nde4").unwrap()).unwrap();
+        assert_eq!(
+            chess.pieces.get(&(5, 4)),
+            Some(&(Piece { piece_type: PieceType::Knight, color: Color::White }))
+        );
+        assert_eq!(chess.pieces.get(&(4, 2)), None);
+        assert_eq!(
+            chess.pieces.get(&(6, 2)),
+            Some(&(Piece { piece_type: PieceType::Knight, color: Color::White }))
+        );
+    }
+
+    #[test]
+    fn cannot_castle_through_check() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((5, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((8, 1), Piece { piece_type: PieceType::Rook, color: Color::White }),
+            ((1, 1), Piece { piece_type: PieceType::Rook, color: Color::White }),
+            ((6, 8), Piece { piece_type: PieceType::Rook, color: Color::Black }),
+            ((1, 8), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let mut chess = Game::from(pieces, Color::White);
+
+        // f1 is controlled by the rook on f8
+        assert_eq!(
+            chess.simulate_move(&Command::parse("O-O").unwrap()).err(),
+            Some(ChessError::CastleThroughCheck)
+        );
+        assert!(chess.simulate_move(&Command::parse("O-O-O").unwrap()).is_ok());
+
+        // castling out of check
+        chess.pieces.remove(&(6, 8));
+        chess.pieces.insert((5, 8), Piece { piece_type: PieceType::Rook, color: Color::Black });
+        assert_eq!(
+            chess.simulate_move(&Command::parse("O-O").unwrap()).err(),
+            Some(ChessError::CastleThroughCheck)
+        );
+        assert_eq!(
+            chess.simulate_move(&Command::parse("O-O-O").unwrap()).err(),
+            Some(ChessError::CastleThroughCheck)
+        );
+    }
+
+    #[test]
+    fn castling_rights_are_lost() {
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "Ke2", "Ke7", "Ke1", "Ke8"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::QueenSide));
+        assert_eq!(chess.play(&Command::parse("O-O").unwrap()), Err(ChessError::InvalidMove));
+
+        let mut chess = Game::new();
+        for command in ["h4", "a5", "Rh3", "Ra6", "Rh1", "Ra8"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        assert!(chess.castling_rights.can_castle(Color::White, Castle::QueenSide));
+        assert!(!chess.castling_rights.can_castle(Color::Black, Castle::QueenSide));
+        assert!(chess.castling_rights.can_castle(Color::Black, Castle::KingSide));
+    }
+
+    #[test]
+    fn kings_cannot_touch() {
+        let pieces: HashMap<(usize, usize), Piece> = vec![
+            ((5, 1), Piece { piece_type: PieceType::King, color: Color::White }),
+            ((5, 3), Piece { piece_type: PieceType::King, color: Color::Black })
+        ]
+            .into_iter()
+            .collect();
+        let chess = Game::from(pieces, Color::White);
+
+        assert_eq!(
+            chess.simulate_move(&Command::parse("Ke2").unwrap()).err(),
+            Some(ChessError::InCheck)
+        );
+        let king = chess.pieces.get(&(5, 1)).unwrap();
+        let moves = king.get_possible_moves((5, 1), &chess.pieces, None);
+        assert_eq!(moves.len(), 2);
+        assert!(moves.iter().all(|m| m.to.1 == 1));
+    }
+
+    #[test]
+    fn exports_fen() {
+        let mut chess = Game::new();
+        assert_eq!(chess.to_fen(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_eq!(chess.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+
+        chess.play(&Command::parse("c5").unwrap()).unwrap();
+        assert_eq!(chess.to_fen(), "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2");
+
+        chess.play(&Command::parse("Nf3").unwrap()).unwrap();
+        assert_eq!(
+            chess.to_fen(),
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        chess.play(&Command::parse("Nc6").unwrap()).unwrap();
+        chess.play(&Command::parse("Rg1").unwrap()).unwrap();
+        assert_eq!(
+            chess.to_fen(),
+            "r1bqkbnr/pp1ppppp/2n5/2p5/4P3/5N2/PPPP1PPP/RNBQKBR1 b Qkq - 3 3"
+        );
+    }
+
+    #[test]
+    fn imports_fen() {
+        let positions = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            "r1bq1rk1/2p1bppp/p1np1n2/1p2p3/4P3/1BP2N2/PP1P1PPP/RNBQR1K1 b - - 0 9",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "8/5k2/8/3pP3/8/8/2K5/8 w - d6 0 40",
+        ];
+        for fen in positions {
+            let game = Game::from_fen(fen).unwrap();
+            assert_eq!(game.to_fen(), fen);
+            assert_eq!(Game::from_fen(&game.to_fen()).unwrap().to_fen(), fen);
+        }
+
+        let game = Game::from_fen(positions[1]).unwrap();
+        assert_eq!(game.turn, Color::White);
+        assert_eq!(
+            game.pieces.get(&(8, 5)),
+            Some(&(Piece { piece_type: PieceType::Queen, color: Color::White }))
+        );
+        assert_eq!(game.halfmove_clock, 4);
+        assert_eq!(game.fullmove_number, 4);
+
+        let invalid = [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - a 1",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        ];
+        for fen in invalid {
+            assert_eq!(Game::from_fen(fen).err(), Some(ChessError::InvalidFen));
+        }
+    }
+
+    #[test]
+    fn colors_have_opposites() {
+        let mut chess = Game::new();
+        assert_eq!(chess.turn.opposite(), Color::Black);
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_eq!(chess.turn.opposite(), Color::White);
+        assert!(!chess.is_check(chess.turn.opposite()));
+    }
+
+    #[test]
+    fn moves_can_be_undone() {
+        let mut chess = Game::new();
+        assert_eq!(chess.undo(), Err(ChessError::NothingToUndo));
+
+        for command in ["e4", "d5", "exd5"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert_eq!(chess.history.len(), 3);
+        assert_eq!(chess.history[2].to_command().to_notation().unwrap(), "exd5");
+        assert_eq!(chess.history[2].captured, Some(Piece::new(PieceType::Pawn, Color::Black)));
+
+        chess.undo().unwrap();
+        assert_eq!(
+            chess.pieces.get(&(4, 5)),
+            Some(&(Piece { piece_type: PieceType::Pawn, color: Color::Black }))
+        );
+        assert_eq!(chess.turn, Color::White);
+        assert_eq!(chess.en_passant, Some((4, 6)));
+
+        chess.undo().unwrap();
+        chess.undo().unwrap();
+        assert!(chess.history.is_empty());
+        assert_eq!(chess.pieces, Game::new().pieces);
+        assert_eq!(chess.to_fen(), Game::new().to_fen());
+        assert_eq!(chess.undo(), Err(ChessError::NothingToUndo));
+
+        // castling rights come back too
+        for command in ["e4", "e5", "Ke2"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        chess.undo().unwrap();
+        assert!(chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+    }
+
+    #[test]
+    fn perft_counts() {
+        let chess = Game::new();
+        assert_eq!(chess.perft(0), 1);
+        assert_eq!(chess.perft(1), 20);
+        assert_eq!(chess.perft(2), 400);
+        assert_eq!(chess.perft(3), 8902);
+
+        let divide = chess.perft_divide(2);
+        assert_eq!(divide.len(), 20);
+        assert!(divide.contains(&("g1f3".to_string(), 20)));
+        assert!(divide.iter().all(|(_, count)| *count == 20));
+        assert_eq!(
+            divide
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u64>(),
+            400
+        );
+
+        // exercises castling, en passant, and promotion
+        let kiwipete = Game::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+        ).unwrap();
+        assert_eq!(kiwipete.perft(1), 48);
+        assert_eq!(kiwipete.perft(2), 2039);
+        let divide = kiwipete.perft_divide(2);
+        assert!(divide.contains(&("e1g1".to_string(), 43)));
+        assert!(divide.contains(&("e5f7".to_string(), 44)));
+
+        let promotions = Game::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1").unwrap();
+        assert_eq!(promotions.perft(1), 24);
+        assert_eq!(promotions.perft(2), 496);
+    }
+
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn simulating_moves_does_not_allocate() {
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+
+        let before = ALLOCATIONS.with(|count| count.get());
+        let moves = chess.get_all_possible_moves(chess.turn);
+        assert!(ALLOCATIONS.with(|count| count.get()) > before);
+        for command in &moves {
+            let before = ALLOCATIONS.with(|count| count.get());
+            let game = chess.simulate_move(command).unwrap();
+            let after = ALLOCATIONS.with(|count| count.get());
+            assert_eq!(after - before, 0, "{} allocated", command.to_notation().unwrap());
+            drop(game);
+        }
+    }
+
+    #[test]
+    fn king_positions_are_cached() {
+        let mut chess = Game::new();
+        assert_eq!(chess.king_position(Color::White), Some((5, 1)));
+        assert_eq!(chess.king_position(Color::Black), Some((5, 8)));
+
+        for command in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Ke7", "O-O", "Kd6"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert_eq!(chess.king_position(Color::White), Some((7, 1)));
+        assert_eq!(chess.king_position(Color::Black), Some((4, 6)));
+
+        chess.undo().unwrap();
+        assert_eq!(chess.king_position(Color::Black), Some((5, 7)));
+
+        let chess = Game::from_fen("8/8/3k4/8/8/8/8/K7 w - - 0 1").unwrap();
+        assert_eq!(chess.king_position(Color::White), Some((1, 1)));
+        assert_eq!(chess.king_position(Color::Black), Some((4, 6)));
+    }
+
+    #[test]
+    fn zobrist_hashes_positions() {
+        let mut first = Game::new();
+        let mut second = Game::new();
+        assert_eq!(first.zobrist_hash(), second.zobrist_hash());
+
+        for command in ["Nf3", "Nf6", "Nc3", "Nc6"] {
+            first.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        for command in ["Nc3", "Nc6", "Nf3", "Nf6"] {
+            second.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert_eq!(first.zobrist_hash(), second.zobrist_hash());
+
+        let before = first.zobrist_hash();
+        first.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_ne!(first.zobrist_hash(), before);
+        first.undo().unwrap();
+        assert_eq!(first.zobrist_hash(), before);
+
+        // side to move, castling rights, and en passant are part of the position
+        let white = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let black = Game::from_fen("4k3/8/8/8/8/8/8/4K2R b K - 0 1").unwrap();
+        let no_castle = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        assert_ne!(white.zobrist_hash(), black.zobrist_hash());
+        assert_ne!(white.zobrist_hash(), no_castle.zobrist_hash());
+
+        let en_passant = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let no_en_passant = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_ne!(en_passant.zobrist_hash(), no_en_passant.zobrist_hash());
+    }
+
+    #[test]
+    fn threefold_repetition_draws() {
+        let mut chess = Game::new();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+
+        for command in shuffle {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        // the starting position has now occurred twice
+        assert!(!chess.is_threefold_repetition());
+        assert_eq!(chess.state, GameState::InProgress);
+
+        for command in &shuffle[..3] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+            assert_eq!(chess.state, GameState::InProgress);
+        }
+        chess.play(&Command::parse("Ng8").unwrap()).unwrap();
+        assert!(chess.is_threefold_repetition());
+        // a third occurrence only lets a player claim the draw
+        assert_eq!(chess.state, GameState::InProgress);
+        assert!(chess.can_claim_draw());
+
+        // the fifth ends the game on its own
+        for _ in 0..2 {
+            for command in shuffle {
+                assert!(!chess.is_fivefold_repetition());
+                chess.play(&Command::parse(command).unwrap()).unwrap();
+            }
+        }
+        assert!(chess.is_fivefold_repetition());
+        assert_eq!(chess.state, GameState::Draw(DrawReason::FivefoldRepetition));
+        assert!(!chess.can_claim_draw());
+    }
+
+    #[test]
+    fn draws_can_be_claimed() {
+        let mut chess = Game::new();
+        assert!(!chess.can_claim_draw());
+        assert_eq!(chess.claim_draw(), Err(ChessError::NoDrawToClaim));
+        assert_eq!(chess.state, GameState::InProgress);
+
+        chess.apply_moves(&["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1", "Ng8"]).unwrap();
+        chess.claim_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::ThreefoldRepetition));
+        assert_eq!(chess.claim_draw(), Err(ChessError::GameOver));
+
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 100 80").unwrap();
+        assert!(chess.can_claim_draw());
+        chess.claim_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::FiftyMove));
+    }
+
+    #[test]
+    fn play_reaches_every_draw_reason() {
+        let play_out = |fen: &str, moves: &[&str]| {
+            let mut chess = Game::from_fen(fen).unwrap();
+            chess.apply_moves(moves).unwrap();
+            chess
+        };
+        let start = Game::new().to_fen();
+        let shuffle = ["Nf3", "Nf6", "Ng1", "Ng8"];
+
+        // these end the game as soon as the move is played
+        let chess = play_out("7k/8/6Q1/8/8/8/8/K7 w - - 0 1", &["Qf7"]);
+        assert_eq!(chess.state, GameState::Draw(DrawReason::Stalemate));
+        let chess = play_out("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", &["Kxd2"]);
+        assert_eq!(chess.state, GameState::Draw(DrawReason::InsufficientMaterial));
+        let chess = play_out("4k3/8/8/8/8/8/4P3/R3K3 w - - 149 80", &["Ra2"]);
+        assert_eq!(chess.state, GameState::Draw(DrawReason::SeventyFiveMove));
+        let chess = play_out(&start, &shuffle.repeat(4));
+        assert_eq!(chess.state, GameState::Draw(DrawReason::FivefoldRepetition));
+
+        // these wait for a player to claim them
+        let mut chess = play_out("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80", &["Ra2"]);
+        assert_eq!(chess.state, GameState::InProgress);
+        chess.claim_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::FiftyMove));
+        let mut chess = play_out(&start, &shuffle.repeat(2));
+        assert_eq!(chess.state, GameState::InProgress);
+        chess.claim_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::ThreefoldRepetition));
+
+        // and this one on both players
+        let mut chess = play_out(&start, &["e4", "e5"]);
+        chess.agree_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::Agreement));
+    }
+
+    #[test]
+    fn repetition_respects_castling_rights() {
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Ke2", "Ke7", "Ke1", "Ke8", "Ke2", "Ke7", "Ke1", "Ke8"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        // the first time this position appeared both sides could still castle
+        assert!(!chess.is_threefold_repetition());
+        assert_eq!(chess.state, GameState::InProgress);
+    }
+
+    #[test]
+    fn move_counters_advance() {
+        let mut chess = Game::new();
+        assert_eq!(chess.fullmove_number, 1);
+        chess.apply_moves(&["e4", "e5"]).unwrap();
+        assert_eq!(chess.fullmove_number, 2);
+        assert_eq!(chess.halfmove_clock, 0);
+
+        // only black's moves finish a full move
+        chess.apply_moves(&["Nf3"]).unwrap();
+        assert_eq!(chess.fullmove_number, 2);
+        assert_eq!(chess.halfmove_clock, 1);
+        assert!(chess.to_fen().ends_with(" 1 2"));
+
+        chess.undo().unwrap();
+        chess.undo().unwrap();
+        assert_eq!(chess.fullmove_number, 1);
+    }
+
+    #[test]
+    fn plies_are_counted() {
+        let mut chess = Game::new();
+        assert_eq!(chess.ply_count(), 0);
+        chess.apply_moves(&["e4", "e5"]).unwrap();
+        assert_eq!(chess.ply_count(), 2);
+        assert_eq!(chess.fullmove_number, 2);
+
+        chess.undo().unwrap();
+        assert_eq!(chess.ply_count(), 1);
+
+        // a position set up from FEN starts counting from zero
+        let chess = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 30").unwrap();
+        assert_eq!(chess.ply_count(), 0);
+    }
+
+    #[test]
+    fn undo_to_rewinds_several_plies() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "d5", "exd5", "Qxd5", "Nc3"]).unwrap();
+        assert_eq!(chess.captured.len(), 2);
+
+        chess.undo_to(2).unwrap();
+        let mut expected = Game::new();
+        expected.apply_moves(&["e4", "d5"]).unwrap();
+        assert_eq!(chess.to_fen(), expected.to_fen());
+        assert_eq!(chess.ply_count(), 2);
+        assert!(chess.captured.is_empty());
+        assert_eq!(chess.history.len(), 2);
+
+        // can't rewind forwards
+        assert_eq!(chess.undo_to(3), Err(ChessError::NothingToUndo));
+
+        chess.undo_to(0).unwrap();
+        assert_eq!(chess.to_fen(), Game::new().to_fen());
+    }
+
+    #[test]
+    fn transpositions_share_a_position_key() {
+        let mut first = Game::new();
+        first.apply_moves(&["Nf3", "Nf6", "Nc3", "Nc6"]).unwrap();
+        let mut second = Game::new();
+        second.apply_moves(&["Nc3", "Nc6", "Nf3", "Nf6"]).unwrap();
+        assert_eq!(first.position_key(), second.position_key());
+        assert_eq!(
+            first.position_key(),
+            "r1bqkb1r/pppppppp/2n2n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R w KQkq -"
+        );
+
+        // the move counters don't matter
+        let mut third = Game::new();
+        third.apply_moves(&["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Nc3", "Nc6"]).unwrap();
+        assert_ne!(first.to_fen(), third.to_fen());
+        assert_eq!(first.position_key(), third.position_key());
+
+        let mut shuffled = Game::new();
+        shuffled.apply_moves(&["Nf3", "Nf6", "Ng1", "Ng8"]).unwrap();
+        assert_eq!(shuffled.position_key(), Game::new().position_key());
+    }
+
+    #[test]
+    fn fifty_move_rule_draws() {
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 98 80").unwrap();
+        chess.play(&Command::parse("Ra2").unwrap()).unwrap();
+        assert_eq!(chess.halfmove_clock, 99);
+        assert!(!chess.is_fifty_move_rule());
+        assert_eq!(chess.state, GameState::InProgress);
+
+        chess.play(&Command::parse("Kd7").unwrap()).unwrap();
+        assert_eq!(chess.halfmove_clock, 100);
+        assert!(chess.is_fifty_move_rule());
+        // fifty moves only make a draw claimable
+        assert_eq!(chess.state, GameState::InProgress);
+        assert!(chess.can_claim_draw());
+
+        // seventy-five end the game without a claim
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 148 80").unwrap();
+        chess.play(&Command::parse("Ra2").unwrap()).unwrap();
+        assert!(!chess.is_seventy_five_move_rule());
+        assert_eq!(chess.state, GameState::InProgress);
+        chess.play(&Command::parse("Kd7").unwrap()).unwrap();
+        assert!(chess.is_seventy_five_move_rule());
+        assert_eq!(chess.state, GameState::Draw(DrawReason::SeventyFiveMove));
+
+        // a pawn move resets the clock
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K3 w - - 99 80").unwrap();
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_eq!(chess.halfmove_clock, 0);
+        assert_eq!(chess.state, GameState::InProgress);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let dead = [
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2K3 w - - 0 1",
+            "4kb2/8/8/8/8/8/8/4K3 w - - 0 1",
+            "2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1",
+        ];
+        for fen in dead {
+            assert!(Game::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+
+        let alive = [
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1",
+            "3bk3/8/8/8/8/8/8/4KB2 w - - 0 1",
+            "4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1",
+        ];
+        for fen in alive {
+            assert!(!Game::from_fen(fen).unwrap().is_insufficient_material(), "{}", fen);
+        }
+
+        // capturing the last pawn ends the game
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        chess.play(&Command::parse("Kxd2").unwrap()).unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::InsufficientMaterial));
+    }
+
+    #[test]
+    fn notation_is_disambiguated() {
+        let chess = Game::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        let moves = chess.get_all_possible_moves(Color::White);
+        let to_d2 = moves
+            .iter()
+            .find(|m| m.to == (4, 2) && m.from == (Some(2), Some(1)))
+            .unwrap();
+        assert_eq!(to_d2.to_notation_on(&chess).unwrap(), "Nbd2");
+        let to_a3 = moves
+            .iter()
+            .find(|m| m.to == (1, 3))
+            .unwrap();
+        assert_eq!(to_a3.to_notation_on(&chess).unwrap(), "Na3");
+
+        let chess = Game::from_fen("K5k1/8/8/8/8/8/8/R6R w - - 0 1").unwrap();
+        let moves = chess.get_all_possible_moves(Color::White);
+        let to_e1 = moves
+            .iter()
+            .find(|m| m.to == (5, 1) && m.from == (Some(8), Some(1)))
+            .unwrap();
+        assert_eq!(to_e1.to_notation_on(&chess).unwrap(), "Rhe1");
+
+        let chess = Game::from_fen("7k/8/8/8/4R3/8/8/K3R3 w - - 0 1").unwrap();
+        let moves = chess.get_all_possible_moves(Color::White);
+        let to_e2 = moves
+            .iter()
+            .find(|m| m.to == (5, 2) && m.from == (Some(5), Some(1)))
+            .unwrap();
+        assert_eq!(to_e2.to_notation_on(&chess).unwrap(), "R1e2");
+
+        let chess = Game::from_fen("K7/8/8/8/4Q2Q/8/k7/7Q w - - 0 1").unwrap();
+        let moves = chess.get_all_possible_moves(Color::White);
+        let to_e1 = moves
+            .iter()
+            .find(|m| m.to == (5, 1) && m.from == (Some(8), Some(4)))
+            .unwrap();
+        assert_eq!(to_e1.to_notation_on(&chess).unwrap(), "Qh4e1");
+
+        // rank disambiguation picks the rook standing on that rank
+        let chess = Game::from_fen("4R3/8/k7/8/8/8/7K/4R3 w - - 0 1").unwrap();
+        let command = Command::parse("R1e4").unwrap();
+        assert_eq!(command.from, (None, Some(1)));
+        let game = chess.simulate_move(&command).unwrap();
+        assert_eq!(game.piece_at("e1"), None);
+        assert_eq!(game.piece_at("e8"), Some(Piece::new(PieceType::Rook, Color::White)));
+        let game = chess.simulate_move(&Command::parse("R8e4").unwrap()).unwrap();
+        assert_eq!(game.piece_at("e1"), Some(Piece::new(PieceType::Rook, Color::White)));
+        assert_eq!(game.piece_at("e8"), None);
+
+        // a partially specified command is resolved against the board
+        let command = Command::parse("Nde4").unwrap();
+        let chess = Game::from_fen("4k3/8/8/8/8/8/3N1N2/4K3 w - - 0 1").unwrap();
+        assert_eq!(command.to_notation_on(&chess).unwrap(), "Nde4");
+    }
+
+    #[test]
+    fn san_adds_check_suffix() {
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+
+        let quiet = CommandBuilder::new().piece(PieceType::Pawn).to((1, 3)).build();
+        assert_eq!(chess.san(&quiet).unwrap(), "a3");
+
+        let check = CommandBuilder::new().piece(PieceType::Bishop).to((6, 7)).takes(true).build();
+        assert_eq!(chess.san(&check).unwrap(), "Bxf7+");
+
+        let mate = CommandBuilder::new().piece(PieceType::Queen).to((6, 7)).takes(true).build();
+        assert_eq!(chess.san(&mate).unwrap(), "Qxf7#");
+    }
+
+    #[test]
+    fn commands_can_parse_uci() {
+        let mut chess = Game::new();
+
+        let command = Command::parse_uci("e2e4", &chess).unwrap();
+        assert_eq!(command.piece, PieceType::Pawn);
+        assert_eq!(command.from, (Some(5), Some(2)));
+        assert_eq!(command.to, (5, 4));
+        assert!(!command.takes);
+        chess.play(&command).unwrap();
+
+        for uci in ["d7d5", "g1f3", "b8c6"] {
+            chess.play(&Command::parse_uci(uci, &chess).unwrap()).unwrap();
+        }
+        let command = Command::parse_uci("e4d5", &chess).unwrap();
+        assert!(command.takes);
+        chess.play(&command).unwrap();
+        assert_eq!(
+            chess.pieces.get(&(4, 5)),
+            Some(&(Piece { piece_type: PieceType::Pawn, color: Color::White }))
+        );
+
+        let chess = Game::from_fen("k7/4P3/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let command = Command::parse_uci("e7e8n", &chess).unwrap();
+        assert_eq!(command.promotion, Some(PieceType::Knight));
+        let game = chess.simulate_move(&command).unwrap();
+        assert_eq!(
+            game.pieces.get(&(5, 8)),
+            Some(&(Piece { piece_type: PieceType::Knight, color: Color::White }))
+        );
+
+        let command = Command::parse_uci("e1g1", &chess).unwrap();
+        assert_eq!(command.castle, Some(Castle::KingSide));
+
+        for invalid in ["", "e2", "e2e9", "e2e4x", "e7e8k", "E2E4", "a3a4"] {
+            assert!(Command::parse_uci(invalid, &chess).is_none(), "{}", invalid);
+        }
+        assert!(Command::parse_uci("d1d2", &Game::new()).is_none());
+    }
+
+    #[test]
+    fn legal_moves_from_square() {
+        let mut chess = Game::new();
+        assert_eq!(chess.legal_moves_from((7, 1)).len(), 2);
+        assert_eq!(chess.legal_moves_from((5, 2)).len(), 2);
+        assert!(chess.legal_moves_from((5, 4)).is_empty());
+        // black pieces can't move on white's turn
+        assert!(chess.legal_moves_from((7, 8)).is_empty());
+
+        for command in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        let king_moves = chess.legal_moves_from((5, 1));
+        assert_eq!(king_moves.len(), 3);
+        assert!(king_moves.iter().any(|m| m.castle == Some(Castle::KingSide)));
+
+        // a pinned knight has nowhere to go
+        let chess = Game::from_fen("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1").unwrap();
+        assert!(chess.legal_moves_from((4, 2)).is_empty());
+        assert_eq!(chess.legal_moves_from((5, 1)).len(), 4);
+    }
+
+    #[test]
+    fn squares_can_be_attacked() {
+        let chess = Game::from_fen("4k3/8/8/3p4/8/8/8/R3K1N1 w - - 0 1").unwrap();
+
+        // pawns attack diagonally forward, not the square in front of them
+        assert!(chess.is_square_attacked((3, 4), Color::Black));
+        assert!(chess.is_square_attacked((5, 4), Color::Black));
+        assert!(!chess.is_square_attacked((4, 4), Color::Black));
+        assert!(!chess.is_square_attacked((4, 6), Color::Black));
+
+        // a rook down an open file, stopped by the first piece
+        assert!(chess.is_square_attacked((1, 8), Color::White));
+        assert!(chess.is_square_attacked((4, 1), Color::White));
+        assert!(!chess.is_square_attacked((8, 1), Color::White));
+
+        // a knight's L-shaped control
+        assert!(chess.is_square_attacked((6, 3), Color::White));
+        assert!(chess.is_square_attacked((8, 3), Color::White));
+        assert!(chess.is_square_attacked((5, 2), Color::White));
+        assert!(!chess.is_square_attacked((7, 3), Color::White));
+    }
+
+    #[test]
+    fn pawns_check_diagonally() {
+        let chess = Game::from_fen("4k3/8/8/3p4/2K5/8/8/8 w - - 0 1").unwrap();
+        assert!(chess.is_check(Color::White));
+
+        let chess = Game::from_fen("4k3/8/8/3p4/3K4/8/8/8 w - - 0 1").unwrap();
+        assert!(!chess.is_check(Color::White));
+
+        // behind or beside the pawn is safe too
+        for fen in ["4k3/8/2K5/3p4/8/8/8/8 w - - 0 1", "4k3/8/8/2Kp4/8/8/8/8 w - - 0 1"] {
+            assert!(!Game::from_fen(fen).unwrap().is_check(Color::White), "{}", fen);
+        }
+
+        let chess = Game::from(
+            HashMap::from([
+                ((5, 8), Piece::new(PieceType::King, Color::Black)),
+                ((1, 1), Piece::new(PieceType::King, Color::White)),
+                ((8, 1), Piece::new(PieceType::Pawn, Color::Black)),
+            ]),
+            Color::White
+        );
+        assert!(!chess.is_check(Color::White));
+        let chess = Game::from_fen("4k3/8/8/8/8/8/4P3/3K4 b - - 0 1").unwrap();
+        assert!(!chess.is_check(Color::White));
+    }
+
+    #[test]
+    fn squares_parse_and_display() {
+        let square = "e4".parse::<Square>().unwrap();
+        assert_eq!(square.file(), 5);
+        assert_eq!(square.rank(), 4);
+        assert_eq!(square.to_string(), "e4");
+        assert_eq!(<(usize, usize)>::from(square), (5, 4));
+
+        let corner = Square::try_from((8, 8)).unwrap();
+        assert_eq!(corner.to_string(), "h8");
+        assert_eq!("a1".parse::<Square>().unwrap(), Square::try_from((1, 1)).unwrap());
+
+        for invalid in ["i9", "a0", "h9", "e", "e44", "E4", ""] {
+            assert_eq!(invalid.parse::<Square>(), Err(ChessError::InvalidSquare), "{}", invalid);
+        }
+        assert_eq!(Square::try_from((0, 4)), Err(ChessError::InvalidSquare));
+        assert_eq!(Square::try_from((5, 9)), Err(ChessError::InvalidSquare));
+
+        // squares work anywhere the tuple API is used
+        let chess = Game::new();
+        let e2: (usize, usize) = "e2".parse::<Square>().unwrap().into();
+        assert_eq!(chess.legal_moves_from(e2).len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn games_round_trip_through_serde() {
+        let chess = Game::new();
+        let json = serde_json::to_string(&chess).unwrap();
+        assert!(json.contains("\"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR\""));
+        let restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.pieces, chess.pieces);
+        assert_eq!(restored.turn, chess.turn);
+        assert_eq!(restored.state, chess.state);
+        assert_eq!(restored.to_fen(), chess.to_fen());
+
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        let json = serde_json::to_string(&chess).unwrap();
+        let mut restored: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.pieces, chess.pieces);
+        assert_eq!(restored.state, GameState::Checkmate(Color::White));
+        assert_eq!(restored.king_position(Color::Black), Some((5, 8)));
+        assert_eq!(restored.history.len(), 7);
+        restored.undo().unwrap();
+        assert_eq!(restored.turn, Color::White);
+
+        assert!(serde_json::from_str::<Game>(&json.replace("PPPP1PPP", "PPPP1PP")).is_err());
+    }
+
+    #[test]
+    fn uci_mode_answers_the_handshake() {
+        let mut game = Game::new();
+        assert_eq!(uci_response(&mut game, "uci").last().unwrap(), "uciok");
+        assert_eq!(uci_response(&mut game, "isready"), vec!["readyok".to_string()]);
+    }
+
+    #[test]
+    fn uci_mode_sets_up_positions() {
+        let mut game = Game::new();
+        uci_response(&mut game, "position startpos moves e2e4 e7e5 g1f3");
+        assert_eq!(game.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+        uci_response(&mut game, "position fen 4k3/8/8/8/8/8/8/4K2R w K - 0 1 moves e1g1");
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/5RK1 b - - 1 1");
+        uci_response(&mut game, "ucinewgame");
+        assert_eq!(game.to_fen(), Game::new().to_fen());
+
+        // a move onto the mover's own piece is refused, along with the ones after it
+        uci_response(&mut game, "position startpos moves d1d2 e2e4");
+        assert_eq!(game.to_fen(), Game::new().to_fen());
+    }
+
+    #[test]
+    fn uci_mode_answers_go_with_a_legal_move() {
+        let mut game = Game::new();
+        uci_response(&mut game, "position fen 4k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        let response = uci_response(&mut game, "go depth 1");
+        let best_move = response[0].strip_prefix("bestmove ").unwrap();
+        let command = Command::parse_uci(best_move, &game).unwrap();
+        assert!(game.play(&command).is_ok());
+        assert_eq!(Command::parse("a8=Q").unwrap().to_uci(&Game::from_fen(
+            "4k3/P7/8/8/8/8/8/4K3 w - - 0 1"
+        ).unwrap()).unwrap(), "a7a8q");
+
+        // the move comes from a search, so a mate in one is found
+        uci_response(&mut game, "position fen 6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert_eq!(uci_response(&mut game, "go depth 2"), vec!["bestmove a1a8".to_string()]);
+        assert_eq!(uci_response(&mut game, "go"), vec!["bestmove a1a8".to_string()]);
+
+        // and a side with no moves says so
+        uci_response(&mut game, "position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert_eq!(uci_response(&mut game, "go depth 2"), vec!["bestmove 0000".to_string()]);
+    }
+
+    #[test]
+    fn best_move_takes_a_free_queen() {
+        let game = Game::from_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+        let best_move = game.best_move(2).unwrap();
+        assert_eq!(best_move.to, (4, 5));
+        assert!(best_move.takes);
+    }
+
+    #[test]
+    fn best_move_finds_mate_in_one() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(game.best_move(2).unwrap().to, (1, 8));
+        assert_eq!(Game::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap().evaluate(), 0);
+    }
+
+    #[test]
+    fn evaluation_text_reports_mates_and_material() {
+        let game = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(2), "Mate in 1");
+
+        // Ra7 shuts the king in, then Rb8 mates
+        let game = Game::from_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(3), "Mate in 2");
+        let game = Game::from_fen("7k/R7/8/8/8/8/8/1R4K1 b - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(2), "Mated in 1");
+        let game = Game::from_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(2), "Checkmate");
+
+        assert_eq!(Game::new().evaluation_text(1), "+0.00");
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(1), "+5.00");
+        let game = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert_eq!(game.evaluation_text(1), "-5.00");
+    }
+
+    #[test]
+    fn material_signatures_ignore_placement() {
+        assert_eq!(Game::new().material_signature(), "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP");
+        let game = Game::from_fen("8/8/3k4/8/8/2Q5/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.material_signature(), "KQvK");
+        let game = Game::from_fen("4k3/8/8/4r3/8/8/1P6/R3K3 w - - 0 1").unwrap();
+        assert_eq!(game.material_signature(), "KRPvKR");
+        assert_eq!(game.mirrored().material_signature(), "KRvKRP");
+    }
+
+    #[test]
+    fn material_balance_counts_piece_values() {
+        let mut game = Game::new();
+        assert_eq!(game.material_balance(), 0);
+        game.pieces.remove(&(1, 8));
+        assert_eq!(game.material_balance(), 5);
+        assert_eq!(PieceType::Queen.value(), 9);
+        assert_eq!(PieceType::King.value(), 0);
+    }
+
+    #[test]
+    fn custom_positions_can_be_set_up_piece_by_piece() {
+        let mut game = Game::new_empty(Color::Black);
+        assert!(game.pieces.is_empty());
+        game.place_piece((8, 8), Piece::new(PieceType::King, Color::Black));
+        game.place_piece((6, 7), Piece::new(PieceType::King, Color::White));
+        game.place_piece((1, 1), Piece::new(PieceType::Pawn, Color::White));
+        assert_eq!(game.remove_piece((1, 1)), Some(Piece::new(PieceType::Pawn, Color::White)));
+        assert_eq!(game.state, GameState::InProgress);
+        game.place_piece((7, 7), Piece::new(PieceType::Queen, Color::White));
+        assert_eq!(game.state, GameState::Checkmate(Color::White));
+    }
+
+    #[test]
+    fn removing_a_rook_gives_up_its_castling_right() {
+        let mut chess = Game::new();
+        chess.remove_piece((6, 1));
+        chess.remove_piece((7, 1));
+        assert!(chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        chess.remove_piece((8, 1));
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        assert!(chess.castling_rights.can_castle(Color::White, Castle::QueenSide));
+        assert!(chess.play(&Command::parse("O-O").unwrap()).is_err());
+
+        // putting the rook back doesn't bring the right back
+        chess.place_piece((8, 1), Piece::new(PieceType::Rook, Color::White));
+        assert!(chess.play(&Command::parse("O-O").unwrap()).is_err());
+
+        // and a king replaced on its square loses both
+        let mut chess = Game::new();
+        chess.place_piece((5, 8), Piece::new(PieceType::Queen, Color::Black));
+        assert!(!chess.castling_rights.can_castle(Color::Black, Castle::KingSide));
+        assert!(!chess.castling_rights.can_castle(Color::Black, Castle::QueenSide));
+    }
+
+    #[test]
+    fn rejects_illegal_positions() {
+        assert!(Game::new().validate().is_ok());
+        let white_king = ((5, 1), Piece::new(PieceType::King, Color::White));
+        let black_king = ((5, 8), Piece::new(PieceType::King, Color::Black));
+        let no_black_king = Game::from(HashMap::from([white_king]), Color::White);
+        assert_eq!(no_black_king.validate(), Err(ChessError::InvalidPosition));
+        let two_white_kings = Game::from(
+            HashMap::from([white_king, black_king, ((4, 1), white_king.1)]),
+            Color::White
+        );
+        assert_eq!(two_white_kings.validate(), Err(ChessError::InvalidPosition));
+
+        for fen in [
+            "4k3/8/8/8/8/8/8/P3K3 w - - 0 1",
+            "3pk3/8/8/8/8/8/8/4K3 w - - 0 1",
+            // black is in check but it is white to move
+            "4k3/8/8/8/8/8/8/4R2K w - - 0 1",
+        ] {
+            assert_eq!(Game::from_fen(fen).err(), Some(ChessError::InvalidPosition), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn exports_pgn() {
+        let mut chess = Game::new();
+        for notation in ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7"] {
+            chess.play(&Command::parse(notation).unwrap()).unwrap();
+        }
+        let pgn = chess.to_pgn();
+        assert!(pgn.starts_with("[Event \"?\"]\n"));
+        assert!(pgn.contains("[Result \"1-0\"]\n\n"));
+        assert!(pgn.ends_with("\n1. e4 e5 2. Bc4 Nc6 3. Qh5 Nf6 4. Qxf7# 1-0"));
+
+        let mut chess = Game::from_fen("4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1").unwrap();
+        for notation in ["Kd7", "Ra7+", "Kc6", "Rhh7"] {
+            chess.play(&Command::parse(notation).unwrap()).unwrap();
+        }
+        let pgn = chess.to_pgn();
+        assert!(pgn.contains("[FEN \"4k3/8/8/8/8/8/8/R3K2R b KQ - 0 1\"]"));
+        assert!(pgn.ends_with("\n1... Kd7 2. Ra7+ Kc6 3. Rhh7 *"));
+    }
+
+    #[test]
+    fn imports_pgn() {
+        let pgn = "[Event \"Casual game\"]\n[White \"?\"]\n[Black \"?\"]\n\n\
+            1. e4 e5 2. Nf3 {the most common reply} Nc6 3. Bb5 a6 (3... Nf6 4. O-O) \
+            4. Ba4 Nf6 5.O-O Be7 6. Re1 b5 7. Bb3 d6 8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 *";
+        let imported = Game::from_pgn(pgn).unwrap();
+
+        let mut chess = Game::new();
+        for notation in [
+            "e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O", "Be7",
+            "Re1", "b5", "Bb3", "d6", "c3", "O-O", "h3", "Nb8", "d4", "Nbd7",
+        ] {
+            chess.play(&Command::parse(notation).unwrap()).unwrap();
+        }
+        assert_eq!(imported.to_fen(), chess.to_fen());
+        assert_eq!(imported.history.len(), 20);
+        assert_eq!(Game::from_pgn(&chess.to_pgn()).unwrap().to_fen(), chess.to_fen());
+
+        assert_eq!(Game::from_pgn("1. e4 e5 2. Ke3 *").err(), Some(ChessError::InvalidMove));
+        // broken movetext is a notation error rather than an illegal move
+        for broken in ["1. e4 e5 2. Nf3) *", "1. e4 (1. d4 *", "1. e4 {open *", "1. e4 Kz9 *"] {
+            let error = Game::from_pgn(broken).err();
+            assert_eq!(error, Some(ChessError::InvalidNotation), "{}", broken);
+        }
+        assert!(Game::from_pgn("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").is_ok());
+        // disambiguation is resolved against the board, as when a promotion makes a second queen
+        let pgn = "[FEN \"4k3/8/8/8/7Q/8/8/1Q4K1 w - - 0 1\"]\n\n1. Qhe1+ *";
+        let imported = Game::from_pgn(pgn).unwrap();
+        assert_eq!(imported.piece_at("e1"), Some(Piece::new(PieceType::Queen, Color::White)));
+        assert_eq!(imported.piece_at("h4"), None);
+        let mut chess = Game::from_fen("4k3/1P6/8/8/7Q/8/8/6K1 w - - 0 1").unwrap();
+        chess.apply_moves(&["b8=Q+", "Kd7", "Qhb4"]).unwrap();
+        let pgn = chess.to_pgn();
+        assert!(pgn.ends_with("1. b8=Q+ Kd7 2. Qhb4 *"));
+        assert_eq!(Game::from_pgn(&pgn).unwrap().to_fen(), chess.to_fen());
+    }
+
+    #[test]
+    fn displays_figurines() {
+        let chess = Game::new();
+        let unicode = format!("{:#}", chess);
+        let lines = unicode.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "8 |♜|♞|♝|♛|♚|♝|♞|♜|");
+        assert_eq!(lines[13], "2 |♙|♙|♙|♙|♙|♙|♙|♙|");
+        assert_eq!(lines[15], "1 |♖|♘|♗|♕|♔|♗|♘|♖|");
+        assert_eq!(format!("{}", chess).lines().nth(15), Some("1 |R|N|B|Q|K|B|N|R|"));
+    }
+
+    #[test]
+    fn displays_coordinates() {
+        let chess = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let white = chess.to_string();
+        let lines = white.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "8 | | | | |K| | | |");
+        assert_eq!(lines[15], "1 |R| | | |K| | | |");
+        assert_eq!(lines[17], "   a b c d e f g h");
+
+        let black = chess.display_for(Color::Black);
+        let lines = black.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "1 | | | |K| | | |R|");
+        assert_eq!(lines[15], "8 | | | |K| | | | |");
+        assert_eq!(lines[17], "   h g f e d c b a");
+    }
+
+    #[test]
+    fn iterates_pieces_in_square_order() {
+        let chess = Game::new();
+        let pieces = chess.iter_pieces().collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 32);
+        assert_eq!(pieces[0], ("a1".parse().unwrap(), Piece::new(PieceType::Rook, Color::White)));
+        assert_eq!(pieces[31], ("h8".parse().unwrap(), Piece::new(PieceType::Rook, Color::Black)));
+        let squares = pieces
+            .iter()
+            .map(|(square, _)| (square.rank(), square.file()))
+            .collect::<Vec<_>>();
+        let mut sorted = squares.clone();
+        sorted.sort();
+        assert_eq!(squares, sorted);
+    }
+
+    #[test]
+    fn move_generation_is_deterministic() {
+        let notations = |game: &Game| {
+            game.get_all_possible_moves(game.turn)
+                .iter()
+                .map(|command| command.to_uci(game).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let chess = Game::new();
+        assert_eq!(notations(&chess), notations(&Game::new()));
+        assert_eq!(notations(&chess)[..4], ["b1c3", "b1a3", "g1h3", "g1f3"]);
+    }
+
+    #[test]
+    fn illegal_moves_report_their_cause() {
+        let chess = Game::new();
+        let error = |notation: &str| chess.simulate_move(&Command::parse(notation).unwrap()).err();
+        assert_eq!(error("Qd4"), Some(ChessError::PathBlocked));
+        assert_eq!(error("O-O"), Some(ChessError::PathBlocked));
+        assert_eq!(error("Ra3"), Some(ChessError::PathBlocked));
+        assert_eq!(error("Nd4"), Some(ChessError::InvalidMove));
+        let knight_move = |from: (usize, usize), to: (usize, usize)| {
+            let command = CommandBuilder::new()
+                .piece(PieceType::Knight)
+                .from((Some(from.0), Some(from.1)))
+                .to(to)
+                .build();
+            chess.simulate_move(&command).err()
+        };
+        assert_eq!(knight_move((4, 5), (4, 4)), Some(ChessError::NoPieceToMove));
+        assert_eq!(knight_move((2, 8), (3, 6)), Some(ChessError::WrongTurn));
+
+        let chess = Game::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let error = |notation: &str| chess.simulate_move(&Command::parse(notation).unwrap()).err();
+        assert_eq!(error("Bc4"), Some(ChessError::NoPieceToMove));
+        let command = CommandBuilder::new()
+            .piece(PieceType::Rook)
+            .to((1, 8))
+            .promotion(Some(PieceType::Queen))
+            .build();
+        assert_eq!(chess.simulate_move(&command).err(), Some(ChessError::IllegalPromotion));
+
+        let chess = Game::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let command = CommandBuilder::new()
+            .piece(PieceType::Pawn)
+            .to((1, 8))
+            .promotion(Some(PieceType::King))
+            .build();
+        assert_eq!(chess.simulate_move(&command).err(), Some(ChessError::IllegalPromotion));
+
+        let chess = Game::from_fen("r3k3/8/8/8/8/8/8/4K3 b q - 0 1").unwrap();
+        let command = Command::parse("O-O-O").unwrap();
+        assert_eq!(chess.simulate_move(&command).err(), None);
+        let chess = Game::from_fen("r3k3/8/8/8/8/8/8/2R1K3 b q - 0 1").unwrap();
+        assert_eq!(chess.simulate_move(&command).err(), Some(ChessError::CastleThroughCheck));
+    }
+
+    #[test]
+    fn pieces_cannot_move_out_of_turn() {
+        let mut chess = Game::new();
+        assert_eq!(chess.play(&Command::parse("e5").unwrap()), Err(ChessError::WrongTurn));
+        assert_eq!(chess.play(&Command::parse("Nf6").unwrap()), Err(ChessError::WrongTurn));
+        assert_eq!(chess.pieces.get(&(5, 7)), Some(&Piece::new(PieceType::Pawn, Color::Black)));
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_eq!(chess.play(&Command::parse("d4").unwrap()), Err(ChessError::WrongTurn));
+        assert!(chess.play(&Command::parse("e5").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn defaults_match_new() {
+        assert_eq!(Game::default(), Game::new());
+        let command = CommandBuilder::default().piece(PieceType::Pawn).to((5, 4)).build();
+        assert_eq!(command.to_notation().unwrap(), "e4");
+    }
+
+    #[test]
+    fn games_compare_by_position() {
+        let start = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(Game::new(), start.unwrap());
+        let mut knights = Game::new();
+        for notation in ["Nf3", "Nf6", "Ng1", "Ng8"] {
+            knights.play(&Command::parse(notation).unwrap()).unwrap();
+        }
+        assert_eq!(knights, Game::new());
+        knights.play(&Command::parse("e4").unwrap()).unwrap();
+        assert_ne!(knights, Game::new());
+        let no_castling = Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1");
+        assert_ne!(no_castling.unwrap(), Game::new());
+    }
+
+    #[test]
+    fn commands_parse_from_str() {
+        let command = "Nf3".parse::<Command>().unwrap();
+        assert_eq!(command.piece, PieceType::Knight);
+        assert_eq!(command.to, (6, 3));
+        let command = "O-O-O".parse::<Command>().unwrap();
+        assert_eq!(command.castle, Some(Castle::QueenSide));
+        assert_eq!("".parse::<Command>().err(), Some(ChessError::NotationTooShort));
+        assert_eq!("e".parse::<Command>().err(), Some(ChessError::NotationTooShort));
+        assert_eq!("hello".parse::<Command>().err(), Some(ChessError::InvalidNotation));
+        assert_eq!("Ni9".parse::<Command>().err(), Some(ChessError::InvalidNotation));
+    }
+
+    #[test]
+    fn converts_between_coords_and_notation() {
+        assert_eq!(Game::coords_from_notation("a1"), Some((1, 1)));
+        assert_eq!(Game::coords_from_notation("h8"), Some((8, 8)));
+        for notation in ["z9", "a0", "i1", "a", "", "e", "e9", "9e", "e44", "E4"] {
+            assert_eq!(Game::coords_from_notation(notation), None, "{}", notation);
+        }
+        assert_eq!(Game::notation_from_coords((1, 1)), Some("a1".to_string()));
+        assert_eq!(Game::notation_from_coords((8, 8)), Some("h8".to_string()));
+        for coords in [(0, 0), (0, 1), (1, 0), (9, 1), (1, 9), (200, 3)] {
+            assert_eq!(Game::notation_from_coords(coords), None);
+        }
+    }
+
+    #[test]
+    fn off_board_targets_are_left_out_of_notation() {
+        let command = CommandBuilder::new().piece(PieceType::Knight).to((6, 3)).build();
+        let command = Command { to: (0, 0), ..command };
+        assert_eq!(command.to_notation(), None);
+        assert_eq!(command.to_uci(&Game::new()), None);
+        let command = Command { to: (9, 3), ..command };
+        assert_eq!(command.to_notation(), None);
+        assert_eq!(command.to_uci(&Game::new()), None);
+
+        // castles still come out whole
+        let castle = CommandBuilder::new()
+            .piece(PieceType::King)
+            .castle(Some(Castle::KingSide))
+            .build();
+        assert_eq!(castle.to_notation().unwrap(), "O-O");
+        assert_eq!(castle.to_uci(&Game::new()).unwrap(), "e1g1");
+    }
+
+    #[test]
+    #[should_panic(expected = "Only castling can be built without a target square")]
+    fn commands_need_a_target_square() {
+        CommandBuilder::new().piece(PieceType::Knight).build();
+    }
+
+    #[test]
+    fn off_board_squares_are_turned_away_when_building() {
+        let knight = || CommandBuilder::new().piece(PieceType::Knight);
+        for builder in [
+            knight().to((0, 0)),
+            knight().to((6, 9)),
+            knight().from((Some(9), None)).to((6, 3)),
+        ] {
+            assert_eq!(builder.try_build().err(), Some(ChessError::InvalidSquare));
+        }
+        assert_eq!(knight().to((6, 3)).try_build().unwrap().to, (6, 3));
+
+        let chess = Game::new();
+        assert!(chess.build_move((5, 2), (5, 9), None).is_none());
+        assert!(chess.build_move((5, 0), (5, 4), None).is_none());
+    }
+
+    #[test]
+    fn reports_checking_pieces() {
+        let mut chess = Game::from_fen("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1").unwrap();
+        assert!(chess.checkers(Color::Black).is_empty());
+        // the knight gives check and uncovers the rook behind it
+        chess.play(&Command::parse("Nd6").unwrap()).unwrap();
+        assert_eq!(chess.checkers(Color::Black), vec![(5, 1), (4, 6)]);
+        assert!(chess.checkers(Color::White).is_empty());
+    }
+
+    #[test]
+    fn reports_pinned_pieces() {
+        let chess = Game::from_fen("4k3/3n4/8/1B6/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(chess.pinned_pieces(Color::Black), vec![(4, 7)]);
+        assert!(chess.pinned_pieces(Color::White).is_empty());
+
+        // two pieces in the way means neither is pinned, and only sliders pin
+        let chess = Game::from_fen("4k3/3n4/2p5/1B6/8/8/4N3/r3K2n w - - 0 1").unwrap();
+        assert!(chess.pinned_pieces(Color::Black).is_empty());
+        assert!(chess.pinned_pieces(Color::White).is_empty());
+        let chess = Game::from_fen("4k3/4r3/8/8/8/8/4N3/q3K3 w - - 0 1").unwrap();
+        assert_eq!(chess.pinned_pieces(Color::White), vec![(5, 2)]);
+    }
+
+    #[test]
+    fn move_generation_simulates_each_move_once() {
+        let mut chess = Game::new();
+        for command in ["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6", "Ng5", "d5", "exd5", "Na5"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+
+        // the same moves as simulating every pseudo-legal move by hand
+        let pseudo_legal = chess.pieces
+            .iter()
+            .filter(|(_, piece)| piece.color == chess.turn)
+            .flat_map(|(coords, piece)| piece.get_possible_moves(*coords, &chess.pieces, None))
+            .chain(
+                [Castle::KingSide, Castle::QueenSide].map(|castle| {
+                    CommandBuilder::new().piece(PieceType::King).castle(Some(castle)).build()
+                })
+            )
+            .filter_map(|command| {
+                let game = chess.simulate_move(&command).ok()?;
+                Some((command.to_notation(), game.is_check(chess.turn.opposite())))
+            })
+            .collect::<Vec<_>>();
+        let before = ALLOCATIONS.with(|count| count.get());
+        let moves = chess.get_all_possible_moves(chess.turn);
+        let allocations = ALLOCATIONS.with(|count| count.get()) - before;
+        let generated = moves
+            .iter()
+            .map(|command| {
+                let notation = (Command { check: None, ..command.clone() }).to_notation();
+                (notation, command.check.is_some())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(generated, pseudo_legal);
+
+        // simulating must not copy the game's history once per move
+        let without_history = Game::from_fen(&chess.to_fen()).unwrap();
+        let before = ALLOCATIONS.with(|count| count.get());
+        without_history.get_all_possible_moves(chess.turn);
+        assert_eq!(ALLOCATIONS.with(|count| count.get()) - before, allocations);
+
+        // the same simulation tells mate apart from check
+        let chess = Game::from_fen("6k1/5ppp/8/8/8/8/2Q5/R5K1 w - - 0 1").unwrap();
+        let moves = chess.get_all_possible_moves(chess.turn);
+        for command in &moves {
+            // san marks check and mate from a simulation of its own
+            assert_eq!(command.to_notation(), chess.san(command).ok());
+        }
+        let notations = moves.iter().filter_map(|command| command.to_notation()).collect::<Vec<_>>();
+        for expected in ["Ra8#", "Qc8#", "Qxh7+"] {
+            assert!(notations.contains(&expected.to_string()), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn game_result() {
+        let mut chess = Game::new();
+        assert!(!chess.is_game_over());
+        assert_eq!(chess.result(), None);
+
+        for command in ["f3", "e5", "g4", "Qh4#"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        assert!(chess.is_game_over());
+        assert_eq!(chess.result(), Some(GameResult::BlackWins));
+
+        let stalemate = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(stalemate.state, GameState::Draw(DrawReason::Stalemate));
+        assert!(stalemate.is_game_over());
+        assert_eq!(stalemate.result(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn chess960_starting_positions() {
+        assert_eq!(Game::new_960(518), Game::new());
+        assert_eq!(
+            Game::new_960(518).to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            Game::new_960(0).to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+        );
+        assert_eq!(
+            Game::new_960(959).to_fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w CAca - 0 1"
+        );
+        let chess = Game::new_960(0);
+        assert_eq!(chess.king_position(Color::White), Some((7, 1)));
+        assert_eq!(Game::from_fen(&chess.to_fen()).unwrap(), chess);
+    }
+
+    #[test]
+    fn chess960_castling() {
+        let mut chess = Game::new_960(0);
+        for command in ["Nc3", "Nc6", "Nd3", "Nd6", "b3", "b6", "Qb2", "Qb7"] {
+            chess.play(&Command::parse(command).unwrap()).unwrap();
+        }
+        // the queen side rook starts on f, right beside the king
+        let castle = Command::parse("O-O-O").unwrap();
+        assert_eq!(castle.to_uci(&chess).unwrap(), "g1f1");
+        assert_eq!(Command::parse_uci("g1f1", &chess).unwrap().castle, Some(Castle::QueenSide));
+        assert_eq!(
+            chess.play(&Command::parse("O-O").unwrap()),
+            Err(ChessError::PathBlocked)
+        );
+        chess.play(&castle).unwrap();
+        assert_eq!(chess.king_position(Color::White), Some((3, 1)));
+        assert_eq!(
+            chess.pieces.get(&(4, 1)),
+            Some(&Piece::new(PieceType::Rook, Color::White))
+        );
+        assert_eq!(chess.pieces.get(&(6, 1)), None);
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::KingSide));
+        assert!(!chess.castling_rights.can_castle(Color::White, Castle::QueenSide));
+
+        // moving the rook that started on f only gives up castling on that side
+        chess.play(&Command::parse("Re8").unwrap()).unwrap();
+        assert!(!chess.castling_rights.can_castle(Color::Black, Castle::QueenSide));
+        assert!(chess.castling_rights.can_castle(Color::Black, Castle::KingSide));
+        assert_eq!(chess.to_fen().split(' ').nth(2), Some("h"));
+        assert_eq!(Game::from_fen(&chess.to_fen()).unwrap(), chess);
+    }
+
+    #[test]
+    fn pieces_can_be_hashed() {
+        let chess = Game::new();
+        let pieces = chess.pieces.values().copied().collect::<HashSet<Piece>>();
+        assert_eq!(pieces.len(), 12);
+        assert!(pieces.contains(&Piece::new(PieceType::Knight, Color::Black)));
+
+        let piece_types = chess.pieces
+            .values()
+            .map(|piece| piece.piece_type)
+            .collect::<HashSet<PieceType>>();
+        assert_eq!(piece_types.len(), 6);
+    }
+
+    #[test]
+    fn applies_a_list_of_moves() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"]).unwrap();
+        assert_eq!(chess.state, GameState::Checkmate(Color::White));
+
+        let mut chess = Game::new();
+        assert_eq!(
+            chess.apply_moves(&["e4", "e5", "Ke3", "Nf6"]),
+            Err(ChessError::MoveListFailed { index: 2, error: Box::new(ChessError::InvalidMove) })
+        );
+        assert_eq!(chess.history.len(), 2);
+        assert_eq!(
+            chess.apply_moves(&["x"]),
+            Err(ChessError::MoveListFailed {
+                index: 0,
+                error: Box::new(ChessError::NotationTooShort),
+            })
+        );
+    }
+
+    #[test]
+    fn sliding_pieces_follow_their_lines() {
+        // a white rook on d4 and bishop on e4, with a black pawn on d6 in the rook's way
+        let chess = Game::from_fen("7k/8/3p4/8/3RB3/8/8/K7 w - - 0 1").unwrap();
+        let attacked = |square: &str| {
+            chess.is_square_attacked(Game::coords_from_notation(square).unwrap(), Color::White)
+        };
+        // horizontal and vertical rook paths in every direction
+        for square in ["a4", "c4", "d1", "d3", "d5", "d6"] {
+            assert!(attacked(square), "{}", square);
+        }
+        assert!(!attacked("d7"));
+        assert!(!attacked("f4"));
+        // diagonal bishop paths in every direction
+        for square in ["h7", "b7", "h1", "b1"] {
+            assert!(attacked(square), "{}", square);
+        }
+        assert!(!attacked("e5"));
+
+        let mut chess = chess;
+        assert_eq!(chess.play(&Command::parse("Rd7").unwrap()), Err(ChessError::PathBlocked));
+        chess.play(&Command::parse("Rxd6").unwrap()).unwrap();
+    }
+
+    #[test]
+    fn captured_pieces_are_listed() {
+        let pawn = |color| Piece::new(PieceType::Pawn, color);
+        let knight = |color| Piece::new(PieceType::Knight, color);
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "d5", "exd5", "Nf6", "Nc3", "Nxd5", "Nxd5"]).unwrap();
+        assert_eq!(chess.captured_by(Color::White), vec![pawn(Color::Black), knight(Color::Black)]);
+        assert_eq!(chess.captured_by(Color::Black), vec![pawn(Color::White)]);
+
+        chess.undo().unwrap();
+        assert_eq!(chess.captured_by(Color::White), vec![pawn(Color::Black)]);
+        chess.undo().unwrap();
+        assert_eq!(chess.captured.len(), 1);
+
+        // en passant takes the pawn beside the target square
+        let mut chess = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        chess.play(&Command::parse("exd6").unwrap()).unwrap();
+        assert_eq!(chess.captured_by(Color::White), vec![pawn(Color::Black)]);
+    }
+
+    #[test]
+    fn pieces_can_be_looked_up_by_square() {
+        let chess = Game::new();
+        assert_eq!(chess.piece_at("e1"), Some(Piece::new(PieceType::King, Color::White)));
+        assert_eq!(chess.piece_at("d8"), Some(Piece::new(PieceType::Queen, Color::Black)));
+        assert_eq!(chess.piece_at("e4"), None);
+        assert_eq!(chess.piece_at("i9"), None);
+        assert_eq!(chess.piece_at(""), None);
+    }
+
+    #[test]
+    fn legal_moves_can_be_detected_without_listing_them() {
+        let chess = Game::new();
+        assert!(chess.has_any_legal_move(Color::White));
+
+        let stalemate = Game::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(!stalemate.has_any_legal_move(Color::Black));
+        assert_eq!(stalemate.state, GameState::Draw(DrawReason::Stalemate));
+
+        let mut checkmate = Game::new();
+        checkmate.apply_moves(&["f3", "e5", "g4", "Qh4#"]).unwrap();
+        assert!(!checkmate.has_any_legal_move(Color::White));
+        assert_eq!(checkmate.state, GameState::Checkmate(Color::Black));
+    }
+
+    #[cfg(feature = "wasm")]
+    #[test]
+    fn wasm_bindings_wrap_a_game() {
+        use chess::wasm::WasmGame;
+
+        let game = WasmGame::new();
+        assert_eq!(game.fen(), Game::new().to_fen());
+        assert_eq!(game.ascii(), Game::new().to_string());
+        let moves = game.legal_moves();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(&"Nf3".to_string()));
+    }
+
+    #[test]
+    fn moves_can_be_built_from_two_squares() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "d5", "Nf3", "Nc6", "Bc4"]).unwrap();
+        assert!(chess.build_move((1, 3), (1, 4), None).is_none());
+
+        let capture = chess.build_move((4, 5), (3, 4), None).unwrap();
+        assert_eq!(capture.piece, PieceType::Pawn);
+        assert!(capture.takes);
+        assert_eq!(chess.san(&capture).unwrap(), "dxc4");
+        chess.play(&capture).unwrap();
+
+        let castle = chess.build_move((5, 1), (7, 1), None).unwrap();
+        assert_eq!(castle.castle, Some(Castle::KingSide));
+        chess.play(&castle).unwrap();
+        assert_eq!(chess.king_position(Color::White), Some((7, 1)));
+
+        let chess = Game::from_fen("3r3k/4P3/8/8/8/8/8/K7 w - - 0 1").unwrap();
+        let promotion = chess.build_move((5, 7), (4, 8), Some(PieceType::Knight)).unwrap();
+        assert!(promotion.takes);
+        assert_eq!(promotion.promotion, Some(PieceType::Knight));
+        assert_eq!(chess.san(&promotion).unwrap(), "exd8=N");
+        let game = chess.simulate_move(&promotion).unwrap();
+        assert_eq!(game.piece_at("d8"), Some(Piece::new(PieceType::Knight, Color::White)));
+    }
+
+    #[test]
+    fn pieces_cannot_take_their_own_side() {
+        let mut chess = Game::new();
+        assert!(chess.build_move((4, 1), (4, 2), None).is_none());
+        let onto_own_pawn = CommandBuilder::new()
+            .piece(PieceType::Queen)
+            .from((Some(4), Some(1)))
+            .to((4, 2))
+            .takes(true)
+            .build();
+        assert_eq!(chess.play(&onto_own_pawn), Err(ChessError::InvalidMove));
+        assert_eq!(chess.play(&Command::parse("Rxa2").unwrap()), Err(ChessError::InvalidMove));
+        assert_eq!(chess.to_fen(), Game::new().to_fen());
+        assert!(chess.captured_by(Color::Black).is_empty());
+    }
+
+    #[test]
+    fn legal_moves_are_listed_in_san() {
+        let moves = Game::new().legal_moves_san();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.contains(&"e4".to_string()));
+        assert!(moves.contains(&"Nf3".to_string()));
+        assert_eq!(moves, Game::new().legal_moves_san());
+
+        let chess = Game::from_fen("6k1/5ppp/8/8/8/8/8/R3R1K1 w - - 0 1").unwrap();
+        let moves = chess.legal_moves_san();
+        assert!(moves.contains(&"Rad1".to_string()));
+        assert!(moves.contains(&"Ra8#".to_string()));
+        assert!(moves.contains(&"Re8#".to_string()));
+    }
+
+    #[test]
+    fn disambiguated_san_parses_back() {
+        let chess = Game::from_fen("4k3/8/8/8/7Q/8/8/1Q2K3 w - - 0 1").unwrap();
+        let moves = chess.legal_moves_san();
+        assert!(moves.contains(&"Qbe4+".to_string()));
+        assert!(moves.contains(&"Qhb4".to_string()));
+        for san in moves {
+            let command = Command::parse(&san).unwrap_or_else(|| panic!("{} should parse", san));
+            assert_eq!(chess.san(&command).unwrap(), san);
+            assert!(chess.is_legal(&command), "{}", san);
+        }
+    }
+
+    #[test]
+    fn games_end_by_resignation_or_agreement() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "e5"]).unwrap();
+        chess.resign(Color::White).unwrap();
+        assert_eq!(chess.state, GameState::Resigned(Color::White));
+        assert_eq!(chess.result(), Some(GameResult::BlackWins));
+        assert!(chess.to_pgn().ends_with("1. e4 e5 0-1"));
+        assert_eq!(chess.play(&Command::parse("Nf3").unwrap()), Err(ChessError::GameOver));
+        assert_eq!(chess.resign(Color::Black), Err(ChessError::GameOver));
+        assert_eq!(chess.agree_draw(), Err(ChessError::GameOver));
+
+        let mut chess = Game::new();
+        chess.agree_draw().unwrap();
+        assert_eq!(chess.state, GameState::Draw(DrawReason::Agreement));
+        assert_eq!(chess.result(), Some(GameResult::Draw));
+        assert_eq!(chess.play(&Command::parse("e4").unwrap()), Err(ChessError::GameOver));
+
+        // a finished game can't be resigned
+        let mut chess = Game::new();
+        chess.apply_moves(&["f3", "e5", "g4", "Qh4#"]).unwrap();
+        assert_eq!(chess.resign(Color::White), Err(ChessError::GameOver));
+    }
+
+    #[test]
+    fn games_can_be_mirrored() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "d5", "exd5", "Nf6", "Ke2", "c5"]).unwrap();
+        let mirrored = chess.mirrored();
+        assert_eq!(mirrored.turn, Color::Black);
+        assert_eq!(mirrored.piece_at("d4"), Some(Piece::new(PieceType::Pawn, Color::Black)));
+        assert_eq!(mirrored.piece_at("e7"), Some(Piece::new(PieceType::King, Color::Black)));
+        assert_eq!(mirrored.king_position(Color::White), Some((5, 1)));
+        assert!(mirrored.castling_rights.can_castle(Color::White, Castle::KingSide));
+        assert!(!mirrored.castling_rights.can_castle(Color::Black, Castle::KingSide));
+        assert_eq!(mirrored.en_passant, Some((3, 3)));
+
+        // material is negated for white, but unchanged for the side to move
+        assert_eq!(chess.material_balance(), 1);
+        assert_eq!(mirrored.material_balance(), -chess.material_balance());
+        assert_eq!(mirrored.evaluate(), chess.evaluate());
+        assert_eq!(mirrored.mirrored(), chess);
+        assert_eq!(mirrored.perft(2), chess.perft(2));
+    }
+
+    #[test]
+    fn pieces_have_unicode_glyphs() {
+        let glyphs = [
+            (PieceType::King, Color::White, '♔'),
+            (PieceType::Queen, Color::White, '♕'),
+            (PieceType::Rook, Color::White, '♖'),
+            (PieceType::Bishop, Color::White, '♗'),
+            (PieceType::Knight, Color::White, '♘'),
+            (PieceType::Pawn, Color::White, '♙'),
+            (PieceType::King, Color::Black, '♚'),
+            (PieceType::Queen, Color::Black, '♛'),
+            (PieceType::Rook, Color::Black, '♜'),
+            (PieceType::Bishop, Color::Black, '♝'),
+            (PieceType::Knight, Color::Black, '♞'),
+            (PieceType::Pawn, Color::Black, '♟'),
+        ];
+        for (piece_type, color, glyph) in glyphs {
+            assert_eq!(Piece::new(piece_type, color).unicode(), glyph);
+        }
+    }
+
+    #[test]
+    fn mobility_and_control() {
+        let chess = Game::new();
+        assert_eq!(chess.mobility(Color::White), 20);
+        assert_eq!(chess.mobility(Color::Black), 20);
+        // every square of the third rank, and the back two ranks apart from the corners
+        let controlled = chess.controlled_squares(Color::White);
+        assert_eq!(controlled.len(), 22);
+        assert!((1..=8).all(|x| controlled.contains(&(x, 3))));
+        assert!(!controlled.contains(&(5, 4)));
+        assert!(!controlled.contains(&(1, 1)));
+
+        // pawns control the diagonals ahead of them, never the square they'd push to
+        let chess = Game::from_fen("4k3/8/8/8/8/4P3/8/K7 w - - 0 1").unwrap();
+        let controlled = chess.controlled_squares(Color::White);
+        let expected = [(4, 4), (6, 4), (1, 2), (2, 2), (2, 1)].into_iter().collect::<HashSet<_>>();
+        assert_eq!(controlled, expected);
+        assert_eq!(chess.mobility(Color::White), 4);
+    }
+
+    #[test]
+    fn legality_checks_agree_with_simulation() {
+        let mut developed = Game::new();
+        developed.apply_moves(&["e4", "e5", "Nf3", "Nc6", "Bc4", "Nf6"]).unwrap();
+        // both knights reach d2, so "Nd2" has to weigh up more than one piece
+        let two_knights = Game::from_fen("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        for chess in [Game::new(), developed, two_knights] {
+            let candidates = chess.pieces
+                .iter()
+                .filter(|(_, piece)| piece.color == chess.turn)
+                .flat_map(|(coords, piece)| piece.get_possible_moves(*coords, &chess.pieces, None))
+                .chain(
+                    [Castle::KingSide, Castle::QueenSide].map(|castle| {
+                        CommandBuilder::new().piece(PieceType::King).castle(Some(castle)).build()
+                    })
+                )
+                .chain(["Ke3", "Qxf7", "Nd2"].map(|notation| Command::parse(notation).unwrap()))
+                .collect::<Vec<_>>();
+            let mut legal = 0;
+            for command in &candidates {
+                let before = ALLOCATIONS.with(|count| count.get());
+                let is_legal = chess.would_be_legal(command);
+                assert_eq!(ALLOCATIONS.with(|count| count.get()) - before, 0);
+                let notation = command.to_notation().unwrap();
+                assert_eq!(is_legal, chess.simulate_move(command).is_ok(), "{}", notation);
+                legal += is_legal as usize;
+            }
+            assert_eq!(legal, chess.get_all_possible_moves(chess.turn).len());
+        }
+        assert_eq!(Game::new().get_all_possible_moves(Color::White).len(), 20);
+    }
+
+    #[test]
+    fn previews_whether_a_move_gives_check() {
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "e5", "Nf3", "d6"]).unwrap();
+        let fen = chess.to_fen();
+
+        assert!(chess.move_gives_check(&Command::parse("Bb5").unwrap()));
+        assert!(!chess.move_gives_check(&Command::parse("Nc3").unwrap()));
+        assert!(!chess.move_gives_check(&Command::parse("Bb6").unwrap()));
+        assert_eq!(chess.to_fen(), fen);
+    }
+
+    #[test]
+    fn pawn_captures_without_a_file_serialize() {
+        let command = CommandBuilder::new().piece(PieceType::Pawn).takes(true).to((4, 5)).build();
+        assert_eq!(command.to_notation(), None);
+        let off_board = Command { from: (Some(9), None), ..command.clone() };
+        assert_eq!(off_board.to_notation(), None);
+
+        // a board fills in the file of the one pawn that can capture
+        let mut chess = Game::new();
+        chess.apply_moves(&["e4", "d5"]).unwrap();
+        assert_eq!(command.to_notation_on(&chess), Ok("exd5".to_string()));
+        assert_eq!(chess.san(&command), Ok("exd5".to_string()));
+        chess.play(&command).unwrap();
+        assert_eq!(chess.piece_at("d5"), Some(Piece::new(PieceType::Pawn, Color::White)));
+
+        // and is ambiguous when two pawns could
+        let chess = Game::from_fen("4k3/8/8/3p4/2P1P3/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(command.to_notation_on(&chess), Err(ChessError::AmbiguousMove));
+        assert_eq!(chess.san(&command), Err(ChessError::AmbiguousMove));
+    }
+
+    #[test]
+    fn random_moves_are_reproducible() {
+        let chess = Game::new();
+        let pick = |seed| chess.random_move_seeded(seed).unwrap().to_notation().unwrap();
+        let first = pick(7);
+        assert_eq!(pick(7), first);
+        assert!(chess.would_be_legal(&Command::parse(&first).unwrap()));
+
+        // twenty legal moves are open from the start, so some seed picks another one
+        assert!((0..32).any(|seed| pick(seed) != first));
+
+        // a whole game replays from one generator
+        let play_out = |seed| {
+            let mut game = Game::new();
+            let mut rng = SplitMix64::new(seed);
+            for _ in 0..20 {
+                match game.random_move(&mut rng) {
+                    Some(command) => game.play(&command).unwrap(),
+                    None => {
+                        break;
+                    }
+                }
+            }
+            game.to_fen()
+        };
+        assert_eq!(play_out(42), play_out(42));
+
+        let mated = Game::from_fen("4R2k/6pp/8/8/8/8/8/K7 b - - 0 1").unwrap();
+        assert!(mated.random_move_seeded(0).is_none());
+    }
+
+    #[test]
+    fn named_openings_load() {
+        let chess = Game::load_opening("Italian").unwrap();
+        assert_eq!(chess.piece_at("c4"), Some(Piece::new(PieceType::Bishop, Color::White)));
+        assert_eq!(chess.piece_at("f3"), Some(Piece::new(PieceType::Knight, Color::White)));
+        assert_eq!(chess.piece_at("c6"), Some(Piece::new(PieceType::Knight, Color::Black)));
+        assert_eq!(chess.turn, Color::Black);
+
+        let chess = Game::load_opening("queen's gambit").unwrap();
+        assert_eq!(chess.history.len(), 3);
+        for name in ["Ruy Lopez", "Sicilian", "French"] {
+            assert!(Game::load_opening(name).is_some());
+        }
+        assert!(Game::load_opening("Bongcloud").is_none());
+    }
+
+    #[test]
+    fn last_move_squares_are_resolved() {
+        let mut chess = Game::new();
+        assert!(chess.last_move().is_none());
+        assert_eq!(chess.last_move_squares(), None);
+
+        chess.play(&Command::parse("Nf3").unwrap()).unwrap();
+        assert_eq!(chess.last_move().unwrap().piece, PieceType::Knight);
+        assert_eq!(chess.last_move_squares(), Some(((7, 1), (6, 3))));
+
+        chess.apply_moves(&["d5", "e4", "dxe4"]).unwrap();
+        assert_eq!(chess.last_move_squares(), Some(((4, 5), (5, 4))));
+
+        chess.apply_moves(&["Be2", "a6", "O-O"]).unwrap();
+        assert_eq!(chess.last_move_squares(), Some(((5, 1), (7, 1))));
+
+        chess.undo().unwrap();
+        assert_eq!(chess.last_move_squares(), Some(((1, 7), (1, 6))));
+    }
+
+    #[test]
+    fn scans_pieces_by_color_and_type() {
+        let chess = Game::new();
+        assert_eq!(chess.pieces_of(Color::White).count(), 16);
+        assert_eq!(chess.pieces_of_type(Color::White, PieceType::Pawn).count(), 8);
+        assert_eq!(
+            chess.pieces_of_type(Color::Black, PieceType::Knight).collect::<Vec<_>>(),
+            vec![
+                ((2, 8), Piece::new(PieceType::Knight, Color::Black)),
+                ((7, 8), Piece::new(PieceType::Knight, Color::Black))
+            ]
+        );
+        assert!(chess.pieces_of(Color::Black).all(|(_, piece)| piece.color == Color::Black));
+    }
+
+    #[test]
+    fn moves_no_piece_can_make_are_rejected() {
+        let mut chess = Game::new();
+        let fen = chess.to_fen();
+        assert_eq!(chess.play(&Command::parse("Bf3").unwrap()), Err(ChessError::InvalidMove));
+        assert_eq!(chess.to_fen(), fen);
+        assert!(chess.history.is_empty());
+
+        // a rook stands on the square the bishop is said to move from
+        let command = CommandBuilder::new()
+            .piece(PieceType::Bishop)
+            .from((Some(1), Some(1)))
+            .to((3, 3))
+            .build();
+        assert_eq!(chess.play(&command), Err(ChessError::NoPieceToMove));
+        assert_eq!(chess.to_fen(), fen);
+
+        // nor can a piece move onto its own square
+        for notation in ["Rxa1", "Ra1", "Qd1", "Raa1"] {
+            assert!(chess.play(&Command::parse(notation).unwrap()).is_err(), "{}", notation);
+        }
+        assert!(Command::parse_uci("a1a1", &chess).is_none());
+        assert_eq!(chess.to_fen(), fen);
+        assert!(chess.history.is_empty());
+    }
+
+    #[test]
+    fn double_check_leaves_only_king_moves() {
+        let mut chess = Game::from_fen("4k3/2b4r/8/8/4N3/8/8/K3R3 w - - 0 1").unwrap();
+        chess.play(&Command::parse("Nd6").unwrap()).unwrap();
+        assert!(chess.is_double_check(Color::Black));
+        assert_eq!(chess.checkers(Color::Black).len(), 2);
+
+        // taking the knight or blocking the rook would only answer one of the two checks
+        let moves = chess.get_all_possible_moves(Color::Black);
+        assert!(moves.iter().all(|command| command.piece == PieceType::King));
+        let mut moves = chess.legal_moves_san();
+        moves.sort();
+        assert_eq!(moves, vec!["Kd7", "Kd8", "Kf8"]);
+
+        assert!(!Game::new().is_double_check(Color::White));
+    }
+
+    #[test]
+    fn move_strings_round_trip() {
+        let mut chess = Game::new();
+        chess
+            .apply_moves(
+                &[
+                    "e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O", "Be7",
+                    "Re1", "b5", "Bb3", "d6", "c3", "O-O", "h3", "Nb8", "d4", "Nbd7",
+                ]
+            )
+            .unwrap();
+        let moves = chess.to_move_string();
+        assert!(moves.starts_with("e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7 f1e1"));
+        assert!(moves.ends_with("h2h3 c6b8 d2d4 b8d7"));
+
+        let replayed = Game::from_move_string(&moves).unwrap();
+        assert_eq!(replayed.to_fen(), chess.to_fen());
+        assert_eq!(replayed.to_move_string(), moves);
+
+        assert_eq!(Game::new().to_move_string(), "");
+        assert_eq!(
+            Game::from_move_string("e2e4 e7e5 e4e5").err(),
+            Some(ChessError::MoveListFailed {
+                index: 2,
+                error: Box::new(ChessError::InvalidMove),
+            })
+        );
+        assert_eq!(
+            Game::from_move_string("e2e4 Nf6").err(),
+            Some(ChessError::MoveListFailed {
+                index: 1,
+                error: Box::new(ChessError::InvalidNotation),
+            })
+        );
+    }
+
+    #[test]
+    fn legality_is_a_plain_bool() {
+        let mut chess = Game::new();
+        assert!(chess.is_legal(&Command::parse("e4").unwrap()));
+        assert!(chess.is_legal(&Command::parse("Nf3").unwrap()));
+        assert!(!chess.is_legal(&Command::parse("e5").unwrap()));
+        assert!(!chess.is_legal(&Command::parse("O-O").unwrap()));
+
+        // black's pawns can't move on white's turn
+        let black_push = CommandBuilder::new()
+            .piece(PieceType::Pawn)
+            .from((Some(5), Some(7)))
+            .to((5, 5))
+            .build();
+        assert!(!chess.is_legal(&black_push));
+        chess.play(&Command::parse("e4").unwrap()).unwrap();
+        assert!(chess.is_legal(&black_push));
+    }
+
+    #[test]
+    fn king_cannot_capture_a_defended_piece() {
+        // the bishop on b4 guards d2, the pawn on f2 has no defender
+        let chess = Game::from_fen("4k3/8/8/8/1b6/8/3p1p2/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            chess.simulate_move(&Command::parse("Kxd2").unwrap()).err(),
+            Some(ChessError::InCheck)
+        );
+        assert!(chess.is_legal(&Command::parse("Kxf2").unwrap()));
+        assert!(chess.legal_moves_san().contains(&String::from("Kxf2")));
+        assert!(!chess.legal_moves_san().contains(&String::from("Kxd2")));
+
+        // a rook behind the pawn defends it through the square the pawn stood on
+        let chess = Game::from_fen("3rk3/8/8/8/8/8/3p4/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            chess.simulate_move(&Command::parse("Kxd2").unwrap()).err(),
+            Some(ChessError::InCheck)
+        );
+    }
+
+    #[test]
+    fn commands_resolve_into_moves() {
+        let chess = Game::new();
+        assert_eq!(
+            chess.resolve(&Command::parse("Nf3").unwrap()),
+            Ok(Move {
+                piece: PieceType::Knight,
+                from: (7, 1),
+                to: (6, 3),
+                captured: None,
+                promotion: None,
+                castle: None,
+                en_passant: false,
+            })
+        );
+        assert_eq!(chess.resolve(&Command::parse("Nf4").unwrap()), Err(ChessError::InvalidMove));
+
+        let chess = Game::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let resolved = chess.resolve(&Command::parse("exd6").unwrap()).unwrap();
+        assert!(resolved.en_passant);
+        assert_eq!(resolved.from, (5, 5));
+        assert_eq!(resolved.captured, Some(Piece::new(PieceType::Pawn, Color::Black)));
+
+        // pawns promote to a queen when the command doesn't say
+        let chess = Game::from_fen("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let resolved = chess.resolve(&Command::parse("axb8").unwrap()).unwrap();
+        assert_eq!(resolved.promotion, Some(PieceType::Queen));
+        assert_eq!(resolved.captured, Some(Piece::new(PieceType::Rook, Color::Black)));
+        assert_eq!(resolved.to_command().to_notation().unwrap(), "axb8=Q");
+
+        let chess = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        let resolved = chess.resolve(&Command::parse("O-O").unwrap()).unwrap();
+        assert_eq!((resolved.from, resolved.to), ((5, 1), (7, 1)));
+        assert_eq!(resolved.castle, Some(Castle::KingSide));
+    }
+}
@@ -0,0 +1,56 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{ Command, Game };
+
+// a thin wrapper so the browser only sees strings, never the engine's own types
+#[wasm_bindgen]
+pub struct WasmGame {
+    game: Game,
+}
+
+impl Default for WasmGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmGame {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmGame {
+        WasmGame { game: Game::new() }
+    }
+
+    #[wasm_bindgen(js_name = fromFen)]
+    pub fn from_fen(fen: &str) -> Result<WasmGame, JsValue> {
+        Game::from_fen(fen)
+            .map(|game| WasmGame { game })
+            .map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    // resolves to the move as SAN, with its check or mate suffix
+    #[wasm_bindgen(js_name = playSan)]
+    pub fn play_san(&mut self, san: &str) -> Result<JsValue, JsValue> {
+        let command = san
+            .parse::<Command>()
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        let notation = self.game
+            .san(&command)
+            .map_err(|error| JsValue::from_str(&error.to_string()))?;
+        self.game.play(&command).map_err(|error| JsValue::from_str(&error.to_string()))?;
+        Ok(JsValue::from_str(&notation))
+    }
+
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Vec<String> {
+        self.game.legal_moves_san()
+    }
+
+    pub fn fen(&self) -> String {
+        self.game.to_fen()
+    }
+
+    pub fn ascii(&self) -> String {
+        self.game.to_string()
+    }
+}